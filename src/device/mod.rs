@@ -5,6 +5,7 @@
 pub mod bus;
 pub mod default;
 pub mod id;
+pub mod net;
 pub mod ps2;
 pub mod storage;
 
@@ -0,0 +1,5 @@
+//! This module implements network interface drivers, registered with [`crate::net`] so the
+//! routing table and `Layer` pipeline can reach real and virtual hardware.
+
+pub mod tap;
+pub mod virtio_net;
@@ -0,0 +1,147 @@
+//! A TAP device is a software [`Interface`] whose "wire" is a character device file instead of
+//! real hardware: a userspace daemon opens it and, by reading and writing raw Ethernet frames,
+//! stands in for whatever a physical link would otherwise carry. This gives user-mode network
+//! stacks, packet capture tools and tests of the `Layer` pipeline something to talk to without
+//! needing a NIC at all.
+//!
+//! Two fixed-size byte rings, wrapped as length-prefixed frame queues by [`crate::net::push_frame`]
+//! and [`crate::net::pop_frame`], carry traffic between the two sides:
+//! - `to_kernel` is filled by [`TapHandle::write`] (userspace injecting a frame) and drained by
+//!   [`TapInterface::read`] (the stack receiving it), the same direction a frame takes arriving
+//!   over a real link.
+//! - `from_kernel` is filled by [`TapInterface::write`] (the stack transmitting a frame) and
+//!   drained by [`TapHandle::read`] (userspace capturing it).
+//!
+//! [`TapInterface`] and [`TapHandle`] share this pair of queues through an [`Arc<Mutex<_>>`],
+//! the same pattern [`crate::file::buffer::pipe::PipeBuffer`]'s callers use to hand a buffer to
+//! two independent file descriptions: the `Interface` lives in [`super::super::super::net::INTERFACES`],
+//! the `DeviceHandle` lives in the device table, and neither owns the other.
+
+use crate::device::Device;
+use crate::device::DeviceHandle;
+use crate::device::DeviceType;
+use crate::device::register_device;
+use crate::errno::Errno;
+use crate::filesystem::path::Path;
+use crate::net::Address;
+use crate::net::BindAddress;
+use crate::net::Interface;
+use crate::net::MAC;
+use crate::net::pop_frame;
+use crate::net::push_frame;
+use crate::net::register_iface;
+use crate::util::container::ring_buffer::RingBuffer;
+use crate::util::container::string::String;
+use crate::util::container::vec::Vec;
+use crate::util::lock::Mutex;
+use crate::util::ptr::arc::Arc;
+
+/// The capacity in bytes of each direction's ring, matching the size `Socket` uses for its own
+/// capture buffer.
+const QUEUE_CAPACITY: usize = 65536;
+
+/// The pair of frame queues shared between a TAP's [`TapInterface`] and [`TapHandle`] sides.
+struct TapQueues {
+	/// Frames written by userspace, waiting to be received by the stack.
+	to_kernel: RingBuffer<u8, Vec<u8>>,
+	/// Frames transmitted by the stack, waiting to be read by userspace.
+	from_kernel: RingBuffer<u8, Vec<u8>>,
+}
+
+/// The [`Interface`] side of a TAP device.
+pub struct TapInterface {
+	/// The name the interface is registered and known under.
+	name: String,
+	/// The interface's MAC address, assigned at creation since there is no hardware to read one
+	/// from.
+	mac: MAC,
+	/// The addresses bound to the interface.
+	addresses: Vec<BindAddress>,
+
+	/// The queues shared with this TAP's [`TapHandle`].
+	queues: Arc<Mutex<TapQueues>>,
+}
+
+impl Interface for TapInterface {
+	fn get_name(&self) -> &[u8] {
+		self.name.as_bytes()
+	}
+
+	fn is_up(&self) -> bool {
+		true
+	}
+
+	fn get_mac(&self) -> &MAC {
+		&self.mac
+	}
+
+	fn get_addresses(&self) -> &[BindAddress] {
+		&self.addresses
+	}
+
+	fn get_addresses_mut(&mut self) -> &mut Vec<BindAddress> {
+		&mut self.addresses
+	}
+
+	fn read(&mut self, buff: &mut [u8]) -> Result<u64, Errno> {
+		let mut queues = self.queues.lock();
+		Ok(pop_frame(&mut queues.to_kernel, buff) as u64)
+	}
+
+	fn write(&mut self, buff: &[u8]) -> Result<u64, Errno> {
+		let mut queues = self.queues.lock();
+		push_frame(&mut queues.from_kernel, buff)?;
+		Ok(buff.len() as u64)
+	}
+}
+
+/// The character device side of a TAP device, exposed to userspace as `/dev/tap<minor>`.
+pub struct TapHandle {
+	/// The queues shared with this TAP's [`TapInterface`].
+	queues: Arc<Mutex<TapQueues>>,
+}
+
+impl DeviceHandle for TapHandle {
+	/// Note: this implementation ignores the offset.
+	fn read(&mut self, _offset: usize, buff: &mut [u8]) -> Result<usize, Errno> {
+		let mut queues = self.queues.lock();
+		Ok(pop_frame(&mut queues.from_kernel, buff))
+	}
+
+	/// Note: this implementation ignores the offset.
+	fn write(&mut self, _offset: usize, buff: &[u8]) -> Result<usize, Errno> {
+		let mut queues = self.queues.lock();
+		push_frame(&mut queues.to_kernel, buff)?;
+		Ok(buff.len())
+	}
+}
+
+/// Creates TAP interface `name`, backed by character device `/dev/tap<minor>` under `major`, and
+/// registers both sides so routes can target the interface by name and a userspace daemon can
+/// drive it through the device file.
+pub fn init(major: u32, minor: u32, name: String, mac: MAC) -> Result<(), Errno> {
+	let queues = Arc::new(Mutex::new(TapQueues {
+		to_kernel: RingBuffer::new(crate::vec![0; QUEUE_CAPACITY]?),
+		from_kernel: RingBuffer::new(crate::vec![0; QUEUE_CAPACITY]?),
+	}))?;
+
+	let path = Path::from_str(crate::format!("/dev/tap{}", minor)?.as_bytes(), false)?;
+	let device = Device::new(
+		major,
+		minor,
+		path,
+		0o660,
+		DeviceType::Char,
+		TapHandle {
+			queues: queues.clone(),
+		},
+	)?;
+	register_device(device).map_err(|_| errno!(EEXIST))?;
+
+	register_iface(TapInterface {
+		name,
+		mac,
+		addresses: Vec::new(),
+		queues,
+	})
+}
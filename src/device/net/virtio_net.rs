@@ -0,0 +1,465 @@
+//! virtio-net is VirtIO's paravirtualized network card, exposed under QEMU (and most other
+//! hypervisors) as a PCI function with vendor ID `0x1af4` and device ID `0x1000`.
+//!
+//! Like [`super::super::storage::virtio_blk`], this module drives the device through the legacy
+//! virtio-pci transport: a single I/O-port BAR carries the feature/status/queue registers, and
+//! the device-specific configuration space (here, the MAC address, then a `u16` status field)
+//! follows immediately after them.
+//!
+//! Two virtqueues are used: queue 0 for received frames (RX), queue 1 for frames to transmit
+//! (TX), each laid out the same way as the single queue in `virtio_blk` (descriptor table then
+//! available ring on one page, used ring on the next). Every buffer on either queue is prefixed
+//! with a `virtio_net_hdr`: since no offload feature is negotiated, this driver always sees the
+//! 10-byte legacy form (no trailing `num_buffers`, which only appears with
+//! `VIRTIO_NET_F_MRG_RXBUF` or `VIRTIO_F_VERSION_1`).
+//!
+//! All of the RX queue's descriptors are posted to the device up front so that frames arriving
+//! with no pending [`Interface::read`] call aren't dropped; [`VirtioNetInterface::read`] waits
+//! for the next one to complete and re-posts its buffer once copied out. The TX queue, like
+//! `virtio_blk`'s, only ever has one request in flight: [`Interface::write`] submits it and
+//! polls the used ring before returning.
+//!
+//! NOTE: this snapshot of the tree does not contain `device::bus::pci`, so there is no way yet
+//! to locate the device's BAR0 by scanning the PCI bus. [`VirtioNetInterface::new`] therefore
+//! takes the I/O base directly rather than discovering it; once PCI enumeration lands, a
+//! `detect` function that walks the bus for vendor `0x1af4` / device `0x1000` and passes BAR0
+//! here should replace that call site.
+
+use crate::errno::Errno;
+use crate::memory;
+use crate::memory::Void;
+use crate::memory::buddy;
+use crate::net::Address;
+use crate::net::BindAddress;
+use crate::net::Interface;
+use crate::net::MAC;
+use crate::net::register_iface;
+use crate::util;
+use crate::util::container::string::String;
+use crate::util::container::vec::Vec;
+use crate::io;
+
+/// The number of descriptors in each virtqueue.
+const QUEUE_SIZE: usize = 8;
+
+/// The maximum size in bytes of an Ethernet frame this driver will copy in or out, including the
+/// `virtio_net_hdr` prefix: a full 1514-byte frame plus the header, rounded up.
+const BUFFER_SIZE: usize = 1536;
+
+/// The length in bytes of the legacy `virtio_net_hdr`, with no `VIRTIO_NET_F_MRG_RXBUF` or
+/// `VIRTIO_F_VERSION_1` negotiated.
+const NET_HDR_LEN: usize = 10;
+
+/// Offset of the 32-bit device features register.
+const REG_DEVICE_FEATURES: u16 = 0x00;
+/// Offset of the 32-bit driver (guest) features register.
+const REG_GUEST_FEATURES: u16 = 0x04;
+/// Offset of the 32-bit queue address register, holding the queue's physical page frame number.
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+/// Offset of the 16-bit, read-only queue size register.
+const REG_QUEUE_SIZE: u16 = 0x0c;
+/// Offset of the 16-bit queue select register.
+const REG_QUEUE_SELECT: u16 = 0x0e;
+/// Offset of the 16-bit queue notify register.
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+/// Offset of the 8-bit device status register.
+const REG_DEVICE_STATUS: u16 = 0x12;
+/// Offset of the device-specific configuration space. For virtio-net, the first field there is
+/// the 6-byte MAC address, followed by the 16-bit link status.
+const REG_DEVICE_CONFIG: u16 = 0x14;
+
+/// Device status bit: the driver has noticed the device.
+const STATUS_ACKNOWLEDGE: u8 = 1;
+/// Device status bit: the driver knows how to drive the device.
+const STATUS_DRIVER: u8 = 2;
+/// Device status bit: the driver has finished feature negotiation.
+const STATUS_FEATURES_OK: u8 = 8;
+/// Device status bit: the driver is ready to drive the device.
+const STATUS_DRIVER_OK: u8 = 4;
+
+/// The number of bits to shift a physical address right by to get the page frame number expected
+/// by `REG_QUEUE_ADDRESS`.
+const QUEUE_ADDR_PFN_SHIFT: u32 = 12;
+
+/// Virtqueue index: received frames.
+const QUEUE_RX: u16 = 0;
+/// Virtqueue index: frames to transmit.
+const QUEUE_TX: u16 = 1;
+
+/// Descriptor flag: the descriptor continues into `next` rather than ending the chain.
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+/// Descriptor flag: the device writes to this buffer (as opposed to reading from it).
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// Configuration status bit: the link is up.
+const NET_S_LINK_UP: u16 = 1;
+
+/// An entry of the descriptor table.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqDesc {
+	/// The physical address of the buffer.
+	addr: u64,
+	/// The length in bytes of the buffer.
+	len: u32,
+	/// A combination of `VIRTQ_DESC_F_*` flags.
+	flags: u16,
+	/// The index of the next descriptor in the chain, if `flags` has `VIRTQ_DESC_F_NEXT` set.
+	next: u16,
+}
+
+/// The ring the driver uses to hand descriptor chains to the device.
+#[repr(C)]
+struct VirtqAvail {
+	/// Driver-side flags. Left at zero: the driver doesn't suppress used-ring notifications.
+	flags: u16,
+	/// The index of the next free slot in `ring`.
+	idx: u16,
+	/// The ring of descriptor chain head indices submitted to the device.
+	ring: [u16; QUEUE_SIZE],
+}
+
+/// An entry of the used ring.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqUsedElem {
+	/// The index of the descriptor chain's head that completed.
+	id: u32,
+	/// The number of bytes written into the chain by the device.
+	len: u32,
+}
+
+/// The ring the device uses to tell the driver which descriptor chains have completed.
+#[repr(C)]
+struct VirtqUsed {
+	/// Device-side flags.
+	flags: u16,
+	/// The index of the next slot the device will write.
+	idx: u16,
+	/// The ring of completed descriptor chains.
+	ring: [VirtqUsedElem; QUEUE_SIZE],
+}
+
+/// The physical memory backing one virtqueue: the descriptor table and available ring on the
+/// first page, the used ring on the second, mirroring the layout `virtio_blk` uses.
+struct VirtQueue {
+	/// The virtual address of the queue's backing memory.
+	mem: *mut Void,
+	/// The value of the used ring's `idx` after the last entry this driver consumed.
+	last_used_idx: u16,
+}
+
+impl VirtQueue {
+	/// Allocates and zeroes a new queue's backing memory.
+	fn new() -> Result<Self, Errno> {
+		let mem = buddy::alloc_kernel(1).map_err(|_| errno!(ENOMEM))?;
+		util::memset(mem as _, 0, memory::PAGE_SIZE * 2);
+
+		Ok(Self {
+			mem,
+			last_used_idx: 0,
+		})
+	}
+
+	/// Returns the physical page frame number of the queue, for `REG_QUEUE_ADDRESS`.
+	fn phys_pfn(&self) -> u32 {
+		((memory::kern_to_phys(self.mem) as usize) >> QUEUE_ADDR_PFN_SHIFT) as u32
+	}
+
+	/// Returns the descriptor table, at the start of the queue's first page.
+	fn desc_table(&self) -> *mut VirtqDesc {
+		self.mem as *mut VirtqDesc
+	}
+
+	/// Returns the available ring, immediately following the descriptor table on the queue's
+	/// first page.
+	fn avail(&self) -> *mut VirtqAvail {
+		unsafe {
+			(self.mem as *mut u8).add(QUEUE_SIZE * core::mem::size_of::<VirtqDesc>())
+				as *mut VirtqAvail
+		}
+	}
+
+	/// Returns the used ring, at the start of the queue's second page (the legacy virtio-pci
+	/// layout requires it to be page-aligned).
+	fn used(&self) -> *mut VirtqUsed {
+		unsafe { (self.mem as *mut u8).add(memory::PAGE_SIZE) as *mut VirtqUsed }
+	}
+
+	/// Writes descriptor `index` and publishes it at the tail of the available ring.
+	fn post(&self, index: u16, desc: VirtqDesc) {
+		unsafe {
+			core::ptr::write(self.desc_table().add(index as usize), desc);
+
+			let avail = self.avail();
+			let avail_idx = core::ptr::read_volatile(&(*avail).idx);
+			core::ptr::write_volatile(&mut (*avail).ring[(avail_idx as usize) % QUEUE_SIZE], index);
+			// Make the descriptor and ring entry visible to the device before publishing the new
+			// `idx`.
+			core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+			core::ptr::write_volatile(&mut (*avail).idx, avail_idx.wrapping_add(1));
+		}
+	}
+
+	/// Returns the head descriptor index and byte length of the next completed chain, if any,
+	/// advancing `last_used_idx` past it.
+	fn poll(&mut self) -> Option<(u16, u32)> {
+		unsafe {
+			let used = self.used();
+			if core::ptr::read_volatile(&(*used).idx) == self.last_used_idx {
+				return None;
+			}
+
+			let elem = core::ptr::read_volatile(
+				&(*used).ring[(self.last_used_idx as usize) % QUEUE_SIZE],
+			);
+			self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+			Some((elem.id as u16, elem.len))
+		}
+	}
+}
+
+/// A virtio-net backed [`Interface`].
+pub struct VirtioNetInterface {
+	/// The base of the device's I/O port BAR.
+	io_base: u16,
+	/// The name of the interface, as registered.
+	name: String,
+	/// The MAC address, read from the device configuration space.
+	mac: MAC,
+	/// The addresses bound to the interface.
+	addresses: Vec<BindAddress>,
+
+	/// The RX virtqueue. Every descriptor is posted up front, each pointing at its own
+	/// `NET_HDR_LEN`-prefixed buffer in `rx_buffers`.
+	rx: VirtQueue,
+	/// The backing buffers for the RX queue's descriptors, one `BUFFER_SIZE` slice per
+	/// descriptor.
+	rx_buffers: *mut Void,
+
+	/// The TX virtqueue. Only ever has at most one request in flight.
+	tx: VirtQueue,
+	/// The backing buffer for the TX queue's single in-flight descriptor.
+	tx_buffer: *mut Void,
+}
+
+// Safe because every access to the device's registers and queue memory is serialized by the
+// `Mutex<Vec<Box<dyn Interface>>>` the interface is registered under.
+unsafe impl Send for VirtioNetInterface {}
+
+impl VirtioNetInterface {
+	/// Initializes the virtio-net device whose I/O port BAR starts at `io_base`, negotiating
+	/// features, reading its MAC and setting up the RX/TX virtqueues.
+	///
+	/// `name` is the name the interface will be registered and known under.
+	///
+	/// See the module documentation for why `io_base` has to be supplied by the caller rather
+	/// than being discovered here.
+	pub fn new(io_base: u16, name: String) -> Result<Self, Errno> {
+		unsafe {
+			// Reset, then walk the status register through the handshake defined by the virtio
+			// spec.
+			io::outb(io_base + REG_DEVICE_STATUS, 0);
+			io::outb(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+			io::outb(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+			// Negotiate no optional feature: in particular `VIRTIO_NET_F_MRG_RXBUF` and
+			// `VIRTIO_NET_F_CSUM` are left unset, keeping every buffer's `virtio_net_hdr` at the
+			// fixed legacy 10-byte length and checksums unoffloaded.
+			let _host_features = io::inl(io_base + REG_DEVICE_FEATURES);
+			io::outl(io_base + REG_GUEST_FEATURES, 0);
+
+			io::outb(
+				io_base + REG_DEVICE_STATUS,
+				STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK,
+			);
+			if io::inb(io_base + REG_DEVICE_STATUS) & STATUS_FEATURES_OK == 0 {
+				return Err(errno!(ENODEV));
+			}
+
+			let mut mac = [0u8; 6];
+			for (i, byte) in mac.iter_mut().enumerate() {
+				*byte = io::inb(io_base + REG_DEVICE_CONFIG + i as u16);
+			}
+
+			for queue_select in [QUEUE_RX, QUEUE_TX] {
+				io::outw(io_base + REG_QUEUE_SELECT, queue_select);
+				let max_queue_size = io::inw(io_base + REG_QUEUE_SIZE);
+				if (max_queue_size as usize) < QUEUE_SIZE {
+					return Err(errno!(ENODEV));
+				}
+			}
+
+			let rx = VirtQueue::new()?;
+			io::outw(io_base + REG_QUEUE_SELECT, QUEUE_RX);
+			io::outl(io_base + REG_QUEUE_ADDRESS, rx.phys_pfn());
+
+			let tx = VirtQueue::new()?;
+			io::outw(io_base + REG_QUEUE_SELECT, QUEUE_TX);
+			io::outl(io_base + REG_QUEUE_ADDRESS, tx.phys_pfn());
+
+			io::outb(
+				io_base + REG_DEVICE_STATUS,
+				STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK,
+			);
+
+			// `QUEUE_SIZE` `BUFFER_SIZE` RX buffers take 8 * 1536 = 12288 bytes, more than two
+			// pages; allocate four (order 2) so every buffer fits.
+			let rx_buffers = buddy::alloc_kernel(2).map_err(|_| errno!(ENOMEM))?;
+			util::memset(rx_buffers as _, 0, memory::PAGE_SIZE * 4);
+			let tx_buffer = buddy::alloc_kernel(0).map_err(|_| errno!(ENOMEM))?;
+			util::memset(tx_buffer as _, 0, memory::PAGE_SIZE);
+
+			let mut iface = Self {
+				io_base,
+				name,
+				mac,
+				addresses: Vec::new(),
+
+				rx,
+				rx_buffers,
+
+				tx,
+				tx_buffer,
+			};
+			iface.post_rx_buffers();
+
+			Ok(iface)
+		}
+	}
+
+	/// Returns the RX buffer backing descriptor `index`.
+	fn rx_buffer(&self, index: u16) -> *mut u8 {
+		unsafe { (self.rx_buffers as *mut u8).add(index as usize * BUFFER_SIZE) }
+	}
+
+	/// Posts every RX descriptor to the device, so incoming frames have somewhere to land before
+	/// the first [`Interface::read`] call.
+	fn post_rx_buffers(&mut self) {
+		for i in 0..QUEUE_SIZE as u16 {
+			let buf = self.rx_buffer(i);
+			self.rx.post(
+				i,
+				VirtqDesc {
+					addr: memory::kern_to_phys(buf as _) as u64,
+					len: BUFFER_SIZE as u32,
+					flags: VIRTQ_DESC_F_WRITE,
+					next: 0,
+				},
+			);
+		}
+	}
+
+	/// Tells whether the device reports its link as up, via the configuration space status
+	/// field following the MAC address.
+	fn link_up(&self) -> bool {
+		unsafe {
+			let lo = io::inb(self.io_base + REG_DEVICE_CONFIG + 6);
+			let hi = io::inb(self.io_base + REG_DEVICE_CONFIG + 7);
+			u16::from_le_bytes([lo, hi]) & NET_S_LINK_UP != 0
+		}
+	}
+}
+
+impl Interface for VirtioNetInterface {
+	fn get_name(&self) -> &[u8] {
+		self.name.as_bytes()
+	}
+
+	fn is_up(&self) -> bool {
+		self.link_up()
+	}
+
+	fn get_mac(&self) -> &MAC {
+		&self.mac
+	}
+
+	fn get_addresses(&self) -> &[BindAddress] {
+		&self.addresses
+	}
+
+	fn get_addresses_mut(&mut self) -> &mut Vec<BindAddress> {
+		&mut self.addresses
+	}
+
+	fn read(&mut self, buff: &mut [u8]) -> Result<u64, Errno> {
+		// Poll for the next completed frame. TODO: wait on the device's IRQ instead once
+		// interrupt routing for PCI devices is wired up, as already noted for `virtio_blk`.
+		let (index, len) = loop {
+			if let Some(entry) = self.rx.poll() {
+				break entry;
+			}
+		};
+
+		let len = (len as usize).saturating_sub(NET_HDR_LEN);
+		let n = len.min(buff.len());
+		unsafe {
+			let src = self.rx_buffer(index).add(NET_HDR_LEN);
+			core::ptr::copy_nonoverlapping(src, buff.as_mut_ptr(), n);
+		}
+
+		// Hand the buffer back to the device for the next frame.
+		let buf = self.rx_buffer(index);
+		self.rx.post(
+			index,
+			VirtqDesc {
+				addr: memory::kern_to_phys(buf as _) as u64,
+				len: BUFFER_SIZE as u32,
+				flags: VIRTQ_DESC_F_WRITE,
+				next: 0,
+			},
+		);
+
+		Ok(n as u64)
+	}
+
+	fn write(&mut self, buff: &[u8]) -> Result<u64, Errno> {
+		if NET_HDR_LEN + buff.len() > BUFFER_SIZE {
+			return Err(errno!(EMSGSIZE));
+		}
+
+		unsafe {
+			let dst = self.tx_buffer as *mut u8;
+			// The `virtio_net_hdr` is left zeroed: no offload is negotiated, so every field
+			// (`flags`, `gso_type`, checksum offsets, ...) stays at its "nothing special" value.
+			util::memset(dst as _, 0, NET_HDR_LEN);
+			core::ptr::copy_nonoverlapping(buff.as_ptr(), dst.add(NET_HDR_LEN), buff.len());
+
+			self.tx.post(
+				0,
+				VirtqDesc {
+					addr: memory::kern_to_phys(dst as _) as u64,
+					len: (NET_HDR_LEN + buff.len()) as u32,
+					flags: 0,
+					next: 0,
+				},
+			);
+		}
+
+		io::outw(self.io_base + REG_QUEUE_NOTIFY, QUEUE_TX);
+
+		// Poll for completion, same limitation as `read`.
+		loop {
+			if self.tx.poll().is_some() {
+				break;
+			}
+		}
+
+		Ok(buff.len() as u64)
+	}
+}
+
+/// Initializes the virtio-net device at I/O port BAR `io_base` and registers it as interface
+/// `name`, giving the `Layer`/routing stack a real backend alongside `lo`.
+pub fn init(io_base: u16, name: String) -> Result<(), Errno> {
+	let iface = VirtioNetInterface::new(io_base, name)?;
+	let mac = *iface.get_mac();
+	register_iface(iface)?;
+
+	crate::println!(
+		"virtio-net: {:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+		mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+	);
+	Ok(())
+}
@@ -0,0 +1,209 @@
+//! The GUID Partition Table (GPT) is the standard partition table format of UEFI systems. Unlike
+//! the MBR, it isn't limited to four partitions nor to 32-bit LBAs, which allows it to describe
+//! disks larger than 2 TiB.
+//!
+//! LBA 0 holds a protective MBR (a single partition of type `0xee`) so that tools which only
+//! understand the MBR format don't mistake the disk for being unpartitioned. The actual table
+//! starts at LBA 1 with a header, followed by an array of partition entries.
+
+use super::Partition;
+use super::Table;
+use crate::device::storage::StorageInterface;
+use crate::errno::Errno;
+use crate::util::container::vec::Vec;
+
+/// The size in bytes of a disk sector.
+const SECTOR_SIZE: u64 = 512;
+
+/// The signature every valid GPT header begins with (the ASCII string `"EFI PART"`, read as a
+/// little-endian `u64`).
+const GPT_SIGNATURE: u64 = 0x5452415020494645;
+
+/// The size in bytes of a partition entry, as parsed by `GPTEntry`. The table on disk may declare
+/// a larger per-entry size; in that case, only the leading `GPT_ENTRY_SIZE` bytes of each entry
+/// are interpreted and the rest is skipped.
+const GPT_ENTRY_SIZE: usize = 128;
+
+/// Computes the CRC-32 (the variant used by GPT, zlib and PKZIP) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+	let mut crc = 0xffffffffu32;
+
+	for &byte in data {
+		crc ^= byte as u32;
+
+		for _ in 0..8 {
+			let mask = (crc & 1).wrapping_neg();
+			crc = (crc >> 1) ^ (0xedb88320 & mask);
+		}
+	}
+
+	!crc
+}
+
+/// The GPT header, located at LBA 1.
+#[repr(C, packed)]
+struct GPTHeader {
+	/// The header's signature, must be equal to `GPT_SIGNATURE`.
+	signature: u64,
+	/// The revision of the GPT format.
+	revision: u32,
+	/// The size in bytes of the header.
+	header_size: u32,
+	/// The CRC32 checksum of the first `header_size` bytes of the header, with this field
+	/// zeroed during the computation.
+	header_crc32: u32,
+	/// Reserved, must be zero.
+	reserved: u32,
+	/// The LBA of this header.
+	current_lba: u64,
+	/// The LBA of the backup header.
+	backup_lba: u64,
+	/// The first usable LBA for partitions.
+	first_usable_lba: u64,
+	/// The last usable LBA for partitions.
+	last_usable_lba: u64,
+	/// The GUID of the disk.
+	disk_guid: [u8; 16],
+	/// The starting LBA of the partition entry array.
+	partition_entry_lba: u64,
+	/// The number of entries in the partition entry array.
+	num_entries: u32,
+	/// The size in bytes of a single entry of the partition entry array.
+	entry_size: u32,
+	/// The CRC32 checksum of the partition entry array.
+	entry_array_crc32: u32,
+}
+
+impl Clone for GPTHeader {
+	fn clone(&self) -> Self {
+		Self {
+			signature: self.signature,
+			revision: self.revision,
+			header_size: self.header_size,
+			header_crc32: self.header_crc32,
+			reserved: self.reserved,
+			current_lba: self.current_lba,
+			backup_lba: self.backup_lba,
+			first_usable_lba: self.first_usable_lba,
+			last_usable_lba: self.last_usable_lba,
+			disk_guid: self.disk_guid,
+			partition_entry_lba: self.partition_entry_lba,
+			num_entries: self.num_entries,
+			entry_size: self.entry_size,
+			entry_array_crc32: self.entry_array_crc32,
+		}
+	}
+}
+
+/// A single entry of the partition entry array.
+#[repr(C, packed)]
+struct GPTEntry {
+	/// The GUID identifying the type of the partition. All zero means the entry is unused.
+	partition_type_guid: [u8; 16],
+	/// The GUID uniquely identifying the partition.
+	unique_guid: [u8; 16],
+	/// The LBA of the first sector of the partition.
+	first_lba: u64,
+	/// The LBA of the last sector of the partition (inclusive).
+	last_lba: u64,
+	/// Attribute flags.
+	attributes: u64,
+	/// The name of the partition, in UTF-16LE.
+	name: [u16; 36],
+}
+
+impl GPTEntry {
+	/// Tells whether the entry is used.
+	fn is_used(&self) -> bool {
+		self.partition_type_guid != [0u8; 16]
+	}
+}
+
+/// Structure representing a GPT partition table.
+pub struct GPTTable {
+	/// The table's header.
+	header: GPTHeader,
+}
+
+impl Table for GPTTable {
+	fn read(storage: &mut dyn StorageInterface) -> Result<Option<Self>, Errno> {
+		if SECTOR_SIZE * 2 > storage.get_size() {
+			return Ok(None);
+		}
+
+		let mut header_buf: [u8; 512] = [0; 512];
+		storage.read_bytes(&mut header_buf, SECTOR_SIZE)?;
+
+		// Valid because the buffer is at least as large as the structure
+		let header = unsafe { &*(header_buf.as_ptr() as *const GPTHeader) };
+		if header.signature != GPT_SIGNATURE {
+			return Ok(None);
+		}
+
+		let header_size = header.header_size as usize;
+		if header_size < core::mem::size_of::<GPTHeader>() || header_size > header_buf.len() {
+			return Ok(None);
+		}
+
+		// Checksums are computed with the `header_crc32` field zeroed out
+		let expected_crc32 = header.header_crc32;
+		let mut crc_buf = header_buf;
+		crc_buf[16..20].fill(0);
+		if crc32(&crc_buf[..header_size]) != expected_crc32 {
+			return Ok(None);
+		}
+
+		let header = header.clone();
+
+		let entries_size = (header.num_entries as u64) * (header.entry_size as u64);
+		let mut entries_buf = crate::vec![0u8; entries_size as usize]?;
+		storage.read_bytes(&mut entries_buf, header.partition_entry_lba * SECTOR_SIZE)?;
+
+		if crc32(&entries_buf) != header.entry_array_crc32 {
+			return Ok(None);
+		}
+
+		Ok(Some(Self {
+			header,
+		}))
+	}
+
+	fn get_type(&self) -> &'static str {
+		"GPT"
+	}
+
+	fn get_partitions(&self, storage: &mut dyn StorageInterface) -> Result<Vec<Partition>, Errno> {
+		let entry_size = self.header.entry_size as usize;
+		let entries_size = (self.header.num_entries as u64) * (self.header.entry_size as u64);
+		let mut entries_buf = crate::vec![0u8; entries_size as usize]?;
+		storage.read_bytes(&mut entries_buf, self.header.partition_entry_lba * SECTOR_SIZE)?;
+
+		let mut partitions = Vec::<Partition>::new();
+
+		for i in 0..(self.header.num_entries as usize) {
+			let off = i * entry_size;
+			if off + GPT_ENTRY_SIZE > entries_buf.len() {
+				break;
+			}
+
+			// Valid because the buffer has at least `GPT_ENTRY_SIZE` bytes left from `off`
+			let entry = unsafe { &*(entries_buf.as_ptr().add(off) as *const GPTEntry) };
+			if !entry.is_used() {
+				continue;
+			}
+
+			let first_lba = entry.first_lba;
+			let last_lba = entry.last_lba;
+			// A malformed or hostile entry can claim a `last_lba` before its `first_lba`, or a
+			// `first_lba` before the header's usable area; either would underflow the sector
+			// count below or yield a bogus, oversized partition, so skip the entry instead.
+			if last_lba < first_lba || first_lba < self.header.first_usable_lba {
+				continue;
+			}
+			let partition = Partition::new(first_lba, last_lba - first_lba + 1);
+			partitions.push(partition)?;
+		}
+
+		Ok(partitions)
+	}
+}
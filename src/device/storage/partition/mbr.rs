@@ -12,6 +12,19 @@ use crate::util::container::vec::Vec;
 /// The signature of the MBR partition table.
 const MBR_SIGNATURE: u16 = 0x55aa;
 
+/// The size in bytes of a disk sector.
+const SECTOR_SIZE: u64 = 512;
+
+/// The maximum number of Extended Boot Records to follow when walking an extended partition's
+/// chain of logical partitions, guarding against a cyclic chain on a malformed disk.
+const MAX_EBR_CHAIN: usize = 1024;
+
+/// Tells whether `type_` is the type of an extended partition, whose `lba_start` points to a
+/// chain of Extended Boot Records rather than to actual partition data.
+fn is_extended_type(type_: u8) -> bool {
+	matches!(type_, 0x05 | 0x0f | 0x85)
+}
+
 /// Structure representing a partition.
 #[repr(C, packed)]
 struct MBRPartition {
@@ -99,19 +112,73 @@ impl Table for MBRTable {
 		"MBR"
 	}
 
-	fn get_partitions(&self, _: &mut dyn StorageInterface) -> Result<Vec<Partition>, Errno> {
+	fn get_partitions(&self, storage: &mut dyn StorageInterface) -> Result<Vec<Partition>, Errno> {
 		let mut partitions = Vec::<Partition>::new();
 
 		for mbr_partition in self.partitions.iter() {
-			if mbr_partition.is_active() {
+			if mbr_partition.parition_type == 0 {
+				continue;
+			}
+
+			if is_extended_type(mbr_partition.parition_type) {
+				Self::read_logical_partitions(
+					storage,
+					mbr_partition.lba_start as u64,
+					&mut partitions,
+				)?;
+				continue;
+			}
+
+			let partition = Partition::new(
+				mbr_partition.lba_start as _,
+				mbr_partition.sectors_count as _,
+			);
+			partitions.push(partition)?;
+		}
+
+		Ok(partitions)
+	}
+}
+
+impl MBRTable {
+	/// Walks the chain of Extended Boot Records describing the logical partitions of an extended
+	/// partition, pushing one `Partition` per logical volume onto `partitions`.
+	///
+	/// `extended_lba` is the start LBA of the extended partition itself, read from its primary
+	/// entry; every EBR's second entry gives the LBA of the next EBR relative to this value.
+	fn read_logical_partitions(storage: &mut dyn StorageInterface, extended_lba: u64,
+		partitions: &mut Vec<Partition>) -> Result<(), Errno> {
+		let mut ebr_lba = extended_lba;
+
+		for _ in 0..MAX_EBR_CHAIN {
+			let mut sector: [u8; 512] = [0; 512];
+			storage.read_bytes(&mut sector, ebr_lba * SECTOR_SIZE)?;
+
+			// Valid because the buffer on the stack has the same size as the structure
+			let ebr = unsafe { &*(sector.as_ptr() as *const MBRTable) };
+			if ebr.signature != MBR_SIGNATURE {
+				break;
+			}
+
+			// Entry 0 describes the logical partition held by this EBR, relative to the EBR
+			// itself
+			let logical = &ebr.partitions[0];
+			if logical.parition_type != 0 {
 				let partition = Partition::new(
-					mbr_partition.lba_start as _,
-					mbr_partition.sectors_count as _,
+					ebr_lba + logical.lba_start as u64,
+					logical.sectors_count as _,
 				);
 				partitions.push(partition)?;
 			}
+
+			// Entry 1, if present, points to the next EBR relative to the extended partition
+			let next = &ebr.partitions[1];
+			if next.parition_type == 0 || !is_extended_type(next.parition_type) {
+				break;
+			}
+			ebr_lba = extended_lba + next.lba_start as u64;
 		}
 
-		Ok(partitions)
+		Ok(())
 	}
 }
@@ -0,0 +1,187 @@
+//! A bus-master IDE DMA transfer path, usable by a PATA driver as an alternative to its PIO path
+//! for a controller whose BAR4 bus-master I/O ports have already been located.
+//!
+//! NOTE: nothing calls into [`DmaController`]: the PIO driver in `device::storage::pata`, set up
+//! by `init_pata`, does not use this module. [`DmaController::new`] takes the bus-master I/O base
+//! directly rather than locating BAR4 itself, the same way [`super::virtio_blk`]'s interface
+//! takes its I/O base directly.
+
+use crate::errno::Errno;
+use crate::io;
+use crate::memory;
+use crate::memory::buddy;
+use crate::memory::Void;
+
+/// The size in bytes of a disk sector.
+const SECTOR_SIZE: usize = 512;
+
+/// A Physical Region Descriptor Table can hold at most this many entries, bounding a single
+/// transfer to `PRD_COUNT` buffer segments.
+const PRD_COUNT: usize = 16;
+
+/// Offset of the 8-bit bus-master command register, relative to the channel's bus-master I/O
+/// base.
+const BM_REG_COMMAND: u16 = 0x0;
+/// Offset of the 8-bit bus-master status register.
+const BM_REG_STATUS: u16 = 0x2;
+/// Offset of the 32-bit bus-master PRDT address register.
+const BM_REG_PRDT_ADDR: u16 = 0x4;
+
+/// Bus-master command register bit: start the transfer.
+const BM_CMD_START: u8 = 1 << 0;
+/// Bus-master command register bit: transfer direction is device-to-memory (a read).
+const BM_CMD_READ: u8 = 1 << 3;
+
+/// Bus-master status register bit: the controller raised its interrupt.
+const BM_STATUS_IRQ: u8 = 1 << 2;
+/// Bus-master status register bit: a transfer error occurred.
+const BM_STATUS_ERROR: u8 = 1 << 1;
+
+/// ATA command: `READ DMA`.
+pub const ATA_CMD_READ_DMA: u8 = 0xc8;
+/// ATA command: `WRITE DMA`.
+pub const ATA_CMD_WRITE_DMA: u8 = 0xca;
+
+/// A single entry of a Physical Region Descriptor Table: a physical buffer address, its byte
+/// count, and a flags word whose high bit is set only on the table's last entry.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Prd {
+	/// The physical address of the buffer segment.
+	addr: u32,
+	/// The segment's byte count. `0` means 64 KiB.
+	byte_count: u16,
+	/// Flags; bit 15 set marks the end of the table.
+	flags: u16,
+}
+
+/// [`Prd::flags`] bit marking the last entry of the table.
+const PRD_FLAG_END_OF_TABLE: u16 = 1 << 15;
+
+/// A bus-master IDE DMA engine attached to one IDE channel.
+pub struct DmaController {
+	/// The base I/O port of the channel's bus-master registers (BAR4, plus `0x8` for the
+	/// secondary channel).
+	bm_base: u16,
+	/// The PRDT, one page, allocated once at creation and reused for every transfer.
+	prdt: *mut Prd,
+	/// The physical address of `prdt`, programmed into the bus-master PRDT address register.
+	prdt_phys: *mut Void,
+}
+
+impl DmaController {
+	/// Creates a new instance driving the bus-master registers at `bm_base`.
+	pub fn new(bm_base: u16) -> Result<Self, Errno> {
+		let prdt = buddy::alloc_kernel(0).map_err(|_| errno!(ENOMEM))? as *mut Prd;
+		let prdt_phys = memory::kern_to_phys(prdt as _) as *mut Void;
+
+		Ok(Self {
+			bm_base,
+			prdt,
+			prdt_phys,
+		})
+	}
+
+	/// Builds the PRDT for a transfer of `len` bytes out of the physically contiguous buffer
+	/// `phys_addr`, splitting it into segments no bigger than a page and not crossing a 64 KiB
+	/// boundary.
+	///
+	/// Fails with `EINVAL` if the buffer needs more than [`PRD_COUNT`] entries, or if any byte of
+	/// it lies at or above the 4 GiB mark the PRD format's 32-bit addresses can reach: the caller
+	/// is expected to fall back to PIO in that case.
+	fn build_prdt(&mut self, phys_addr: *const Void, len: usize) -> Result<(), Errno> {
+		if (phys_addr as usize as u64) + (len as u64) > 1u64 << 32 {
+			return Err(errno!(EINVAL));
+		}
+
+		let mut remaining = len;
+		let mut addr = phys_addr as usize;
+		let mut i = 0;
+
+		while remaining > 0 {
+			if i >= PRD_COUNT {
+				return Err(errno!(EINVAL));
+			}
+
+			let until_64k_boundary = 0x10000 - (addr % 0x10000);
+			let chunk = remaining.min(until_64k_boundary).min(memory::PAGE_SIZE);
+			let byte_count = if chunk == 0x10000 { 0 } else { chunk as u16 };
+			let flags = if remaining == chunk {
+				PRD_FLAG_END_OF_TABLE
+			} else {
+				0
+			};
+
+			unsafe {
+				*self.prdt.add(i) = Prd {
+					addr: addr as u32,
+					byte_count,
+					flags,
+				};
+			}
+
+			addr += chunk;
+			remaining -= chunk;
+			i += 1;
+		}
+
+		Ok(())
+	}
+
+	/// Performs a DMA transfer of `count` sectors, in or out of the physically contiguous buffer
+	/// at `phys_addr`, using `command` (`ATA_CMD_READ_DMA` or `ATA_CMD_WRITE_DMA`).
+	///
+	/// The caller is responsible for having already selected the drive and written its LBA/sector
+	/// count registers, and for issuing `command` on the IDE command register once this function
+	/// has programmed the bus-master side. Completion is detected by polling the bus-master status
+	/// register rather than waiting for the IDE IRQ, since this tree has no primitive yet to park
+	/// the caller on an interrupt.
+	pub fn transfer(
+		&mut self,
+		phys_addr: *const Void,
+		count: usize,
+		command: u8,
+	) -> Result<(), Errno> {
+		let len = count * SECTOR_SIZE;
+		self.build_prdt(phys_addr, len)?;
+
+		unsafe {
+			io::outb(self.bm_base + BM_REG_COMMAND, 0);
+			io::outl(self.bm_base + BM_REG_PRDT_ADDR, self.prdt_phys as u32);
+
+			// Clear any error/IRQ bits latched by a previous transfer before starting this one.
+			let status = io::inb(self.bm_base + BM_REG_STATUS);
+			io::outb(
+				self.bm_base + BM_REG_STATUS,
+				status | BM_STATUS_IRQ | BM_STATUS_ERROR,
+			);
+
+			let mut cmd = BM_CMD_START;
+			if command == ATA_CMD_READ_DMA {
+				cmd |= BM_CMD_READ;
+			}
+			io::outb(self.bm_base + BM_REG_COMMAND, cmd);
+		}
+
+		loop {
+			let status = unsafe { io::inb(self.bm_base + BM_REG_STATUS) };
+			if status & BM_STATUS_IRQ != 0 {
+				unsafe {
+					io::outb(self.bm_base + BM_REG_COMMAND, 0);
+					io::outb(self.bm_base + BM_REG_STATUS, status);
+				}
+
+				if status & BM_STATUS_ERROR != 0 {
+					return Err(errno!(EIO));
+				}
+				return Ok(());
+			}
+		}
+	}
+}
+
+impl Drop for DmaController {
+	fn drop(&mut self) {
+		buddy::free_kernel(self.prdt as _, 0);
+	}
+}
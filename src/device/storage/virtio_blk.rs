@@ -0,0 +1,392 @@
+//! virtio-blk is VirtIO's paravirtualized block device. Under QEMU (and most other
+//! hypervisors) it is exposed as a PCI function with vendor ID `0x1af4` and device ID `0x1001`,
+//! and is far faster than emulated PATA since it skips the IDE register dance entirely.
+//!
+//! This module drives the device through the legacy virtio-pci transport: a single I/O-port
+//! BAR carries the feature/status/queue registers, and the device-specific configuration space
+//! (here, the disk capacity) follows immediately after them.
+//!
+//! A single virtqueue is used for all I/O. It is one physically contiguous, page-aligned
+//! region holding, in order: the descriptor table, the available ring (written by the driver),
+//! padding up to the next page, then the used ring (written by the device). A block request is
+//! a chain of three descriptors: a 16-byte request header (`type`, a reserved `u32`, and the
+//! 64-bit starting sector), the data buffer, and a 1-byte status the device fills in once the
+//! request completes. The driver hands a chain to the device by pushing its head descriptor's
+//! index into the available ring and writing the queue's notification register, then waits for
+//! the used ring to advance.
+//!
+//! NOTE: this snapshot of the tree does not contain `device::bus::pci`, so there is no way yet
+//! to locate the device's BAR0 by scanning the PCI bus. [`VirtioBlkInterface::new`] therefore
+//! takes the I/O base directly rather than discovering it; once PCI enumeration lands, a
+//! `detect` function that walks the bus for vendor `0x1af4` / device `0x1001` and passes BAR0
+//! here should replace that call site.
+
+use crate::device::Device;
+use crate::device::DeviceHandle;
+use crate::device::DeviceType;
+use crate::device::register_device;
+use crate::device::storage::StorageInterface;
+use crate::errno::Errno;
+use crate::filesystem::path::Path;
+use crate::io;
+use crate::memory;
+use crate::memory::Void;
+use crate::memory::buddy;
+use crate::util;
+
+/// The size in bytes of a disk sector.
+const SECTOR_SIZE: u64 = 512;
+
+/// The number of descriptors in the virtqueue. Only one request is ever in flight (the driver
+/// polls for completion before returning), so a fixed, small queue is enough.
+const QUEUE_SIZE: usize = 16;
+
+/// Offset of the 32-bit device features register.
+const REG_DEVICE_FEATURES: u16 = 0x00;
+/// Offset of the 32-bit driver (guest) features register.
+const REG_GUEST_FEATURES: u16 = 0x04;
+/// Offset of the 32-bit queue address register, holding the queue's physical page frame number.
+const REG_QUEUE_ADDRESS: u16 = 0x08;
+/// Offset of the 16-bit, read-only queue size register.
+const REG_QUEUE_SIZE: u16 = 0x0c;
+/// Offset of the 16-bit queue select register.
+const REG_QUEUE_SELECT: u16 = 0x0e;
+/// Offset of the 16-bit queue notify register.
+const REG_QUEUE_NOTIFY: u16 = 0x10;
+/// Offset of the 8-bit device status register.
+const REG_DEVICE_STATUS: u16 = 0x12;
+/// Offset of the device-specific configuration space. For virtio-blk, the first field there is
+/// the 64-bit disk capacity, in sectors.
+const REG_DEVICE_CONFIG: u16 = 0x14;
+
+/// Device status bit: the driver has noticed the device.
+const STATUS_ACKNOWLEDGE: u8 = 1;
+/// Device status bit: the driver knows how to drive the device.
+const STATUS_DRIVER: u8 = 2;
+/// Device status bit: the driver has finished feature negotiation.
+const STATUS_FEATURES_OK: u8 = 8;
+/// Device status bit: the driver is ready to drive the device.
+const STATUS_DRIVER_OK: u8 = 4;
+
+/// The number of bits to shift a physical address right by to get the page frame number expected
+/// by `REG_QUEUE_ADDRESS`.
+const QUEUE_ADDR_PFN_SHIFT: u32 = 12;
+
+/// Descriptor flag: the descriptor continues into `next` rather than ending the chain.
+const VIRTQ_DESC_F_NEXT: u16 = 1;
+/// Descriptor flag: the device writes to this buffer (as opposed to reading from it).
+const VIRTQ_DESC_F_WRITE: u16 = 2;
+
+/// Request type: read sectors from the disk into the data buffer.
+const BLK_T_IN: u32 = 0;
+/// Request type: write sectors from the data buffer to the disk.
+const BLK_T_OUT: u32 = 1;
+
+/// An entry of the descriptor table.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqDesc {
+	/// The physical address of the buffer.
+	addr: u64,
+	/// The length in bytes of the buffer.
+	len: u32,
+	/// A combination of `VIRTQ_DESC_F_*` flags.
+	flags: u16,
+	/// The index of the next descriptor in the chain, if `flags` has `VIRTQ_DESC_F_NEXT` set.
+	next: u16,
+}
+
+/// The ring the driver uses to hand descriptor chains to the device.
+#[repr(C)]
+struct VirtqAvail {
+	/// Driver-side flags. Left at zero: the driver doesn't suppress used-ring notifications.
+	flags: u16,
+	/// The index of the next free slot in `ring`.
+	idx: u16,
+	/// The ring of descriptor chain head indices submitted to the device.
+	ring: [u16; QUEUE_SIZE],
+}
+
+/// An entry of the used ring.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct VirtqUsedElem {
+	/// The index of the descriptor chain's head that completed.
+	id: u32,
+	/// The number of bytes written into the chain by the device.
+	len: u32,
+}
+
+/// The ring the device uses to tell the driver which descriptor chains have completed.
+#[repr(C)]
+struct VirtqUsed {
+	/// Device-side flags.
+	flags: u16,
+	/// The index of the next slot the device will write.
+	idx: u16,
+	/// The ring of completed descriptor chains.
+	ring: [VirtqUsedElem; QUEUE_SIZE],
+}
+
+/// The 16-byte header placed ahead of the data buffer in every request.
+#[repr(C, packed)]
+struct BlkReqHeader {
+	/// `BLK_T_IN` or `BLK_T_OUT`.
+	type_: u32,
+	/// Reserved, must be zero.
+	reserved: u32,
+	/// The starting sector of the request.
+	sector: u64,
+}
+
+/// A virtio-blk backed [`StorageInterface`].
+pub struct VirtioBlkInterface {
+	/// The base of the device's I/O port BAR.
+	io_base: u16,
+	/// The virtual address of the queue's backing memory: the descriptor table and available
+	/// ring on the first page, the used ring on the second.
+	queue: *mut Void,
+	/// The value of the used ring's `idx` after the last request this driver waited on.
+	last_used_idx: u16,
+	/// The disk's capacity, in sectors, read from the device configuration space.
+	capacity: u64,
+}
+
+// Safe because every access to the device's registers and queue memory is serialized by the
+// `Mutex<Device>` the interface is registered under.
+unsafe impl Send for VirtioBlkInterface {}
+
+impl VirtioBlkInterface {
+	/// Initializes the virtio-blk device whose I/O port BAR starts at `io_base`, negotiating
+	/// features and setting up its single virtqueue.
+	///
+	/// See the module documentation for why `io_base` has to be supplied by the caller rather
+	/// than being discovered here.
+	pub fn new(io_base: u16) -> Result<Self, Errno> {
+		unsafe {
+			// Reset, then walk the status register through the handshake defined by the virtio
+			// spec.
+			io::outb(io_base + REG_DEVICE_STATUS, 0);
+			io::outb(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE);
+			io::outb(io_base + REG_DEVICE_STATUS, STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+			// Negotiate no optional feature: the device's offered bits (`VIRTIO_BLK_F_RO`,
+			// `VIRTIO_BLK_F_SIZE_MAX`, ...) are all left unset, keeping the device in its
+			// simplest, plain read/write, 512-byte-sector shape.
+			let _host_features = io::inl(io_base + REG_DEVICE_FEATURES);
+			io::outl(io_base + REG_GUEST_FEATURES, 0);
+
+			io::outb(
+				io_base + REG_DEVICE_STATUS,
+				STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK,
+			);
+			if io::inb(io_base + REG_DEVICE_STATUS) & STATUS_FEATURES_OK == 0 {
+				return Err(errno!(ENODEV));
+			}
+
+			io::outw(io_base + REG_QUEUE_SELECT, 0);
+			let max_queue_size = io::inw(io_base + REG_QUEUE_SIZE);
+			if (max_queue_size as usize) < QUEUE_SIZE {
+				return Err(errno!(ENODEV));
+			}
+
+			let queue = buddy::alloc_kernel(1).map_err(|_| errno!(ENOMEM))?;
+			util::memset(queue as _, 0, memory::PAGE_SIZE * 2);
+
+			let queue_phys = memory::kern_to_phys(queue);
+			io::outl(
+				io_base + REG_QUEUE_ADDRESS,
+				((queue_phys as usize) >> QUEUE_ADDR_PFN_SHIFT) as u32,
+			);
+
+			io::outb(
+				io_base + REG_DEVICE_STATUS,
+				STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK,
+			);
+
+			let mut capacity_bytes = [0u8; 8];
+			for (i, byte) in capacity_bytes.iter_mut().enumerate() {
+				*byte = io::inb(io_base + REG_DEVICE_CONFIG + i as u16);
+			}
+			let capacity = u64::from_le_bytes(capacity_bytes);
+
+			Ok(Self {
+				io_base,
+				queue,
+				last_used_idx: 0,
+				capacity,
+			})
+		}
+	}
+
+	/// Returns the descriptor table, at the start of the queue's first page.
+	fn desc_table(&self) -> *mut VirtqDesc {
+		self.queue as *mut VirtqDesc
+	}
+
+	/// Returns the available ring, immediately following the descriptor table on the queue's
+	/// first page.
+	fn avail(&self) -> *mut VirtqAvail {
+		unsafe {
+			(self.queue as *mut u8).add(QUEUE_SIZE * core::mem::size_of::<VirtqDesc>())
+				as *mut VirtqAvail
+		}
+	}
+
+	/// Returns the used ring, at the start of the queue's second page (the legacy virtio-pci
+	/// layout requires it to be page-aligned).
+	fn used(&self) -> *mut VirtqUsed {
+		unsafe { (self.queue as *mut u8).add(memory::PAGE_SIZE) as *mut VirtqUsed }
+	}
+
+	/// Returns a pointer to scratch space, carved out of the unused tail of the used ring's page,
+	/// used to hold the request header and status byte of the in-flight request.
+	fn scratch(&self) -> *mut u8 {
+		unsafe { (self.used() as *mut u8).add(core::mem::size_of::<VirtqUsed>()) }
+	}
+
+	/// Builds a `header` -> `buf` -> status descriptor chain, submits it to the device and polls
+	/// the used ring until it completes.
+	///
+	/// `device_writes` must be set for reads (the device fills `buf`) and cleared for writes (the
+	/// device only reads `buf`).
+	///
+	/// `buf`'s physical address is read off as-is: a buffer crossing a page boundary into
+	/// non-contiguous physical memory would corrupt the transfer, the same limitation noted for
+	/// the PATA DMA path in [`super::pata_dma`].
+	fn submit(&mut self, req_type: u32, sector: u64, buf: *mut u8, len: usize,
+		device_writes: bool) -> Result<(), Errno> {
+		unsafe {
+			let header = self.scratch() as *mut BlkReqHeader;
+			core::ptr::write(
+				header,
+				BlkReqHeader {
+					type_: req_type,
+					reserved: 0,
+					sector,
+				},
+			);
+			let status = self.scratch().add(core::mem::size_of::<BlkReqHeader>());
+			core::ptr::write_volatile(status, 0xff);
+
+			let desc = self.desc_table();
+			core::ptr::write(
+				desc.add(0),
+				VirtqDesc {
+					addr: memory::kern_to_phys(header as _) as u64,
+					len: core::mem::size_of::<BlkReqHeader>() as u32,
+					flags: VIRTQ_DESC_F_NEXT,
+					next: 1,
+				},
+			);
+			core::ptr::write(
+				desc.add(1),
+				VirtqDesc {
+					addr: memory::kern_to_phys(buf as _) as u64,
+					len: len as u32,
+					flags: VIRTQ_DESC_F_NEXT
+						| if device_writes {
+							VIRTQ_DESC_F_WRITE
+						} else {
+							0
+						},
+					next: 2,
+				},
+			);
+			core::ptr::write(
+				desc.add(2),
+				VirtqDesc {
+					addr: memory::kern_to_phys(status as _) as u64,
+					len: 1,
+					flags: VIRTQ_DESC_F_WRITE,
+					next: 0,
+				},
+			);
+
+			let avail = self.avail();
+			let avail_idx = core::ptr::read_volatile(&(*avail).idx);
+			core::ptr::write_volatile(
+				&mut (*avail).ring[(avail_idx as usize) % QUEUE_SIZE],
+				0, // the chain's head descriptor is always slot 0
+			);
+			// Make the descriptor chain and ring entry visible to the device before publishing
+			// the new `idx`.
+			core::sync::atomic::fence(core::sync::atomic::Ordering::SeqCst);
+			core::ptr::write_volatile(&mut (*avail).idx, avail_idx.wrapping_add(1));
+
+			io::outw(self.io_base + REG_QUEUE_NOTIFY, 0);
+
+			// Poll for completion. TODO: wait on the device's IRQ instead once interrupt
+			// routing for PCI devices is wired up, as already noted for the PATA DMA path.
+			let used = self.used();
+			while core::ptr::read_volatile(&(*used).idx) == self.last_used_idx {}
+			self.last_used_idx = core::ptr::read_volatile(&(*used).idx);
+
+			if core::ptr::read_volatile(status) != 0 {
+				return Err(errno!(EIO));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Returns the disk's capacity in sectors, as read from the device configuration space.
+	pub fn get_blocks_count(&self) -> u64 {
+		self.capacity
+	}
+}
+
+impl StorageInterface for VirtioBlkInterface {
+	fn get_size(&self) -> u64 {
+		self.capacity * SECTOR_SIZE
+	}
+
+	fn read_bytes(&mut self, buf: &mut [u8], offset: u64) -> Result<(), Errno> {
+		if offset % SECTOR_SIZE != 0 || buf.len() as u64 % SECTOR_SIZE != 0 {
+			return Err(errno!(EINVAL));
+		}
+
+		self.submit(BLK_T_IN, offset / SECTOR_SIZE, buf.as_mut_ptr(), buf.len(), true)
+	}
+
+	fn write_bytes(&mut self, buf: &[u8], offset: u64) -> Result<(), Errno> {
+		if offset % SECTOR_SIZE != 0 || buf.len() as u64 % SECTOR_SIZE != 0 {
+			return Err(errno!(EINVAL));
+		}
+
+		// Safe: the device only reads from this buffer for a `BLK_T_OUT` request.
+		self.submit(BLK_T_OUT, offset / SECTOR_SIZE, buf.as_ptr() as *mut u8, buf.len(), false)
+	}
+}
+
+impl DeviceHandle for VirtioBlkInterface {
+	fn read(&mut self, offset: usize, buff: &mut [u8]) -> Result<usize, Errno> {
+		self.read_bytes(buff, offset as u64)?;
+		Ok(buff.len())
+	}
+
+	fn write(&mut self, offset: usize, buff: &[u8]) -> Result<usize, Errno> {
+		self.write_bytes(buff, offset as u64)?;
+		Ok(buff.len())
+	}
+}
+
+/// Initializes the virtio-blk device at I/O port BAR `io_base` and registers it as block device
+/// `major` (minor `0`), giving the existing partition/`Table` code a fast backend alongside PATA.
+pub fn init(io_base: u16, major: u32) -> Result<(), Errno> {
+	let iface = VirtioBlkInterface::new(io_base)?;
+	let blocks_count = iface.get_blocks_count();
+
+	let device = Device::new(
+		major,
+		0,
+		Path::from_str(b"/dev/vda", false)?,
+		0o660,
+		DeviceType::Block,
+		iface,
+	)?;
+	register_device(device).map_err(|_| errno!(EEXIST))?;
+
+	crate::println!("virtio-blk: {} sectors", blocks_count);
+	Ok(())
+}
@@ -125,6 +125,10 @@ impl IO for PipeBuffer {
 	}
 
 	/// Note: This implemention ignores the offset.
+	///
+	/// On an empty pipe with at least one writing end still open, this returns a zero-length read
+	/// with `eof` unset: it neither parks the caller nor fails with `EAGAIN`. Unlike `write`, it
+	/// takes no `O_NONBLOCK` flag.
 	fn read(&mut self, _: u64, buf: &mut [u8]) -> Result<(u64, bool), Errno> {
 		let len = self.buffer.read(buf);
 		let eof = self.write_ends == 0 && self.get_data_len() == 0;
@@ -135,16 +139,43 @@ impl IO for PipeBuffer {
 	}
 
 	/// Note: This implemention ignores the offset.
-	fn write(&mut self, _: u64, buf: &[u8]) -> Result<u64, Errno> {
-		if self.read_ends > 0 {
-			let len = self.buffer.write(buf);
+	///
+	/// Per POSIX, a write of at most `limits::PIPE_BUF` bytes is atomic: it is never interleaved
+	/// with another writer's and never partially committed. Writes above that threshold may still
+	/// make partial progress.
+	///
+	/// `nonblock` is the writing file description's `O_NONBLOCK` flag: when the write can't be
+	/// satisfied atomically right now, it selects between returning `EAGAIN` immediately and
+	/// registering the calling process on `POLLOUT` before returning `EAGAIN` so the syscall layer
+	/// parks it and retries the whole write once space frees up.
+	///
+	/// Note: `write` is called with the pipe already locked by the IO layer, and
+	/// `add_waiting_process` only registers interest in `POLLOUT` — it does not itself yield or
+	/// drop that lock. So this cannot loop in place re-checking
+	/// `get_available_len`: that would re-register the caller every iteration while holding the
+	/// lock that a reader needs to drain the buffer, hanging forever. Registering once and
+	/// returning lets the syscall layer release the lock, sleep, and call back in.
+	fn write(&mut self, _: u64, buf: &[u8], nonblock: bool) -> Result<u64, Errno> {
+		if self.read_ends == 0 {
+			return Err(errno!(EPIPE));
+		}
 
-			self.block_handler.wake_processes(io::POLLIN);
+		if buf.len() <= limits::PIPE_BUF && buf.len() > self.get_available_len() {
+			if !nonblock {
+				let proc_mutex = Process::get_current().ok_or_else(|| errno!(EPIPE))?;
+				let mut proc_guard = proc_mutex.lock();
+				self.block_handler
+					.add_waiting_process(proc_guard.get_mut(), io::POLLOUT)?;
+			}
 
-			Ok(len as _)
-		} else {
-			Err(errno!(EPIPE))
+			return Err(errno!(EAGAIN));
 		}
+
+		let len = self.buffer.write(buf);
+
+		self.block_handler.wake_processes(io::POLLIN);
+
+		Ok(len as _)
 	}
 
 	fn poll(&mut self, mask: u32) -> Result<u32, Errno> {
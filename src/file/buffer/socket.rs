@@ -3,16 +3,41 @@
 use core::ffi::c_void;
 use crate::errno::Errno;
 use crate::file::buffer::BlockHandler;
+use crate::net;
+use crate::net::PacketBinding;
+use crate::process::mem_space::ptr::SyscallPtr;
+use crate::process::mem_space::ptr::SyscallString;
 use crate::process::mem_space::MemSpace;
 use crate::syscall::ioctl;
+use crate::util::container::ring_buffer::RingBuffer;
+use crate::util::container::string::String;
 use crate::util::FailableDefault;
+use crate::util::io;
 use crate::util::io::IO;
+use crate::util::lock::Mutex;
+use crate::util::ptr::arc::Arc;
 use crate::util::ptr::IntSharedPtr;
 use super::Buffer;
 
 /// The maximum size of a socket's buffers.
 const BUFFER_SIZE: usize = 65536;
 
+/// Address family: `AF_PACKET`, link-layer raw sockets.
+const AF_PACKET: i32 = 17;
+/// Socket type: `SOCK_RAW`, no protocol framing on top of what `domain` already describes.
+const SOCK_RAW: i32 = 3;
+
+/// `ioctl` request binding this `AF_PACKET`/`SOCK_RAW` socket to a single interface's traffic.
+/// `argp` points to the interface's name, NUL-terminated.
+///
+/// Driver-private: no standard `SIOC*` number covers binding a raw socket to an interface by
+/// `ioctl` (real `AF_PACKET` sockets do it through `bind(2)`, which this tree's socket layer has
+/// nowhere yet to plumb a `sockaddr_ll` through).
+const SIOCPKTBINDIFACE: u32 = 0x8970;
+/// `ioctl` request restricting this `AF_PACKET`/`SOCK_RAW` socket to a single EtherType. `argp`
+/// points to a host-byte-order `u16`.
+const SIOCPKTSETPROTO: u32 = 0x8971;
+
 /// Structure representing a socket.
 #[derive(Debug)]
 pub struct Socket {
@@ -23,23 +48,43 @@ pub struct Socket {
 	/// The socket's protocol.
 	protocol: i32,
 
+	/// This socket's `AF_PACKET`/`SOCK_RAW` capture state: the interface/EtherType filter it is
+	/// bound to and the frames matching it, shared with [`net::PACKET_SOCKETS`] so [`net::receive`]
+	/// can deliver into it. `None` for every other domain/type combination.
+	packet: Option<Arc<Mutex<PacketBinding>>>,
+
 	/// The socket's block handler.
 	block_handler: BlockHandler,
 }
 
 impl Socket {
 	/// Creates a new instance.
-	pub fn new(domain: i32, type_: i32, protocol: i32) -> Self {
+	pub fn new(domain: i32, type_: i32, protocol: i32) -> Result<Self, Errno> {
 		// TODO Check domain, type and protocol. Use EINVAL, EPROTOTYPE and
 		// EPROTONOSUPPORT
 
-		Self {
+		let packet = if domain == AF_PACKET && type_ == SOCK_RAW {
+			let binding = Arc::new(Mutex::new(PacketBinding {
+				iface: None,
+				ethertype: None,
+				rx: RingBuffer::new(crate::vec![0; BUFFER_SIZE]?),
+			}))?;
+			net::register_packet_socket(binding.clone())?;
+
+			Some(binding)
+		} else {
+			None
+		};
+
+		Ok(Self {
 			domain,
 			type_,
 			protocol,
 
+			packet,
+
 			block_handler: BlockHandler::new(),
-		}
+		})
 	}
 
 	/// Returns the socket's domain.
@@ -64,7 +109,7 @@ impl Socket {
 impl FailableDefault for Socket {
 	fn failable_default() -> Result<Self, Errno> {
 		// TODO Put correct params (unix domain)
-		Ok(Self::new(0, 0, 0))
+		Self::new(0, 0, 0)
 	}
 }
 
@@ -85,12 +130,33 @@ impl Buffer for Socket {
 
 	fn ioctl(
 		&mut self,
-		_mem_space: IntSharedPtr<MemSpace>,
-		_request: ioctl::Request,
-		_argp: *const c_void,
+		mem_space: IntSharedPtr<MemSpace>,
+		request: ioctl::Request,
+		argp: *const c_void,
 	) -> Result<u32, Errno> {
-		// TODO
-		todo!();
+		let packet = self.packet.as_ref().ok_or_else(|| errno!(ENOTTY))?;
+
+		match request.get_old_format() {
+			SIOCPKTBINDIFACE => {
+				let mem_space_guard = mem_space.lock();
+				let name: SyscallString = (argp as usize).into();
+				let name = name.get(&mem_space_guard)?.ok_or_else(|| errno!(EFAULT))?;
+
+				packet.lock().iface = Some(String::try_from(name)?);
+			}
+
+			SIOCPKTSETPROTO => {
+				let mem_space_guard = mem_space.lock();
+				let ethertype: SyscallPtr<u16> = (argp as usize).into();
+				let ethertype = ethertype.get(&mem_space_guard)?.ok_or_else(|| errno!(EFAULT))?;
+
+				packet.lock().ethertype = Some(*ethertype);
+			}
+
+			_ => return Err(errno!(ENOTTY)),
+		}
+
+		Ok(0)
 	}
 }
 
@@ -101,19 +167,39 @@ impl IO for Socket {
 	}
 
 	/// Note: This implemention ignores the offset.
-	fn read(&mut self, _: u64, _buf: &mut [u8]) -> Result<(u64, bool), Errno> {
-		// TODO
-		todo!();
+	fn read(&mut self, _: u64, buf: &mut [u8]) -> Result<(u64, bool), Errno> {
+		let packet = self.packet.as_ref().ok_or_else(|| errno!(ENOTTY))?;
+		let n = net::pop_frame(&mut packet.lock().rx, buf);
+
+		Ok((n as u64, false))
 	}
 
 	/// Note: This implemention ignores the offset.
-	fn write(&mut self, _: u64, _buf: &[u8]) -> Result<u64, Errno> {
-		// TODO
-		todo!();
+	///
+	/// Hands `buf` straight to the bound interface's `Interface::write`, so it is sent exactly as
+	/// given: the caller is responsible for having built a well-formed link-layer frame.
+	fn write(&mut self, _: u64, buf: &[u8], _nonblock: bool) -> Result<u64, Errno> {
+		let packet = self.packet.as_ref().ok_or_else(|| errno!(ENOTTY))?;
+		let binding = packet.lock();
+		let iface_name = binding.iface.as_ref().ok_or_else(|| errno!(EDESTADDRREQ))?;
+
+		net::with_iface_mut(iface_name.as_bytes(), |iface| iface.write(buf))
+			.ok_or_else(|| errno!(ENODEV))?
 	}
 
-	fn poll(&mut self, _mask: u32) -> Result<u32, Errno> {
-		// TODO
-		todo!();
+	fn poll(&mut self, mask: u32) -> Result<u32, Errno> {
+		let packet = self.packet.as_ref().ok_or_else(|| errno!(ENOTTY))?;
+
+		let mut result = 0;
+		if mask & io::POLLIN != 0 && packet.lock().rx.get_data_len() > 0 {
+			result |= io::POLLIN;
+		}
+		if mask & io::POLLOUT != 0 {
+			// A raw socket's "transmit" is just handing the frame to the bound interface, which
+			// this tree's `Interface::write` never blocks on.
+			result |= io::POLLOUT;
+		}
+
+		Ok(result)
 	}
 }
@@ -0,0 +1,119 @@
+//! This module implements the `fd/` subdirectory found under each `/proc/[pid]`, listing the
+//! process's open file descriptors as symlinks, the way [`super::proc_dir::ProcDir`] builds the
+//! rest of a per-process directory.
+//!
+//! NOTE: [`super::proc_dir::ProcDir`] does not create an [`FdDir`], so `fd/` does not appear
+//! under a `/proc/[pid]` directory; [`super::ProcFS::get_inode`] materializes a real link node for
+//! the entries of any [`FdDir`] node present in the filesystem.
+
+use crate::errno::Errno;
+use crate::file::fs::kernfs::node::KernFSNode;
+use crate::file::DirEntry;
+use crate::file::FileContent;
+use crate::file::FileType;
+use crate::file::Mode;
+use crate::process;
+use crate::process::pid::Pid;
+use crate::util::container::hashmap::HashMap;
+use crate::util::container::string::String;
+use crate::util::io::IO;
+use crate::util::ptr::cow::Cow;
+
+/// A node representing `/proc/[pid]/fd`.
+///
+/// Unlike [`super::proc_dir::ProcDir`], this node holds only the owning `pid`, not a snapshot of
+/// its descriptor table: every read reflects the table as it is at that instant.
+#[derive(Debug)]
+pub struct FdDir {
+	/// The PID owning the descriptor table this directory lists.
+	pid: Pid,
+}
+
+impl FdDir {
+	/// Creates a new instance for the process with the given `pid`.
+	pub fn new(pid: Pid) -> Self {
+		Self { pid }
+	}
+
+	/// Returns the PID owning the descriptor table this directory lists.
+	pub fn pid(&self) -> Pid {
+		self.pid
+	}
+}
+
+impl KernFSNode for FdDir {
+	fn get_mode(&self) -> Mode {
+		0o500
+	}
+
+	fn get_content<'a>(&'a self) -> Cow<'a, FileContent> {
+		// Built on demand from the live fd table rather than cached here: a stored `Directory`
+		// entries map would go stale the moment a descriptor is opened or closed.
+		let mut entries = HashMap::new();
+		let Some(proc_mutex) = process::get_scheduler().lock().get_process(self.pid) else {
+			return FileContent::Directory(entries).into();
+		};
+		let proc_guard = proc_mutex.lock();
+		let proc = proc_guard.get();
+		let Some(fds_mutex) = proc.get_fds() else {
+			return FileContent::Directory(entries).into();
+		};
+		let fds_guard = fds_mutex.lock();
+
+		for fd in fds_guard.get().iter() {
+			let Ok(name) = crate::format!("{}", fd.get_id()) else {
+				continue;
+			};
+			let _ = entries.insert(
+				name,
+				DirEntry {
+					// A placeholder, non-kernfs inode: opening the entry goes through
+					// `super::ProcFS::get_inode`, which materializes a real `FileContent::Link`
+					// node pointing at this fd's backing file, rather than through this value.
+					inode: fd.get_id() as _,
+					entry_type: FileType::Link,
+				},
+			);
+		}
+
+		FileContent::Directory(entries).into()
+	}
+}
+
+/// Resolves the symlink target of `/proc/[pid]/fd/<fd_num>`: the path of the file description
+/// currently open on file descriptor `fd_num` of process `pid`.
+///
+/// Returns `None` if `pid` no longer names a live process or `fd_num` isn't currently open in it,
+/// in which case the caller should report `ENOENT`.
+pub fn resolve_target(pid: Pid, fd_num: u32) -> Option<String> {
+	let proc_mutex = process::get_scheduler().lock().get_process(pid)?;
+	let proc_guard = proc_mutex.lock();
+	let proc = proc_guard.get();
+	let fds_mutex = proc.get_fds()?;
+	let fds_guard = fds_mutex.lock();
+
+	let fd = fds_guard.get().iter().find(|fd| fd.get_id() == fd_num)?;
+	let file_mutex = fd.get_file();
+	let file_guard = file_mutex.lock();
+	let path = file_guard.get().get_path();
+
+	path.as_bytes().try_into().ok()
+}
+
+impl IO for FdDir {
+	fn get_size(&self) -> u64 {
+		0
+	}
+
+	fn read(&mut self, _offset: u64, _buf: &mut [u8]) -> Result<(u64, bool), Errno> {
+		Err(errno!(EISDIR))
+	}
+
+	fn write(&mut self, _offset: u64, _buf: &[u8], _nonblock: bool) -> Result<u64, Errno> {
+		Err(errno!(EISDIR))
+	}
+
+	fn poll(&mut self, _mask: u32) -> Result<u32, Errno> {
+		Err(errno!(EINVAL))
+	}
+}
@@ -0,0 +1,77 @@
+//! `hidepid=`/`gid=` mount-option parsing and enforcement for [`super::ProcFS`], restricting
+//! which callers can see another process's `/proc/[pid]` directory.
+
+use crate::file::perm::Gid;
+use crate::file::perm::Uid;
+use crate::process::Process;
+
+/// The visibility policy applied to another process's `/proc/[pid]` directory, as configured by
+/// the `hidepid=`/`gid=` mount options.
+#[derive(Debug, Clone, Copy)]
+pub struct HidepidPolicy {
+	/// The `hidepid=` level:
+	/// - `0`: everyone can see every `/proc/[pid]` (the default).
+	/// - `1`: a `/proc/[pid]` whose owner isn't the caller still appears when the root directory
+	///   is enumerated, but looking it up fails with `ENOENT`, so neither the directory itself
+	///   nor its contents can be reached.
+	/// - `2`: additionally, such a `/proc/[pid]` doesn't appear at all when the root directory is
+	///   enumerated.
+	pub level: u8,
+	/// The group allowed to see every process regardless of `level`, set by `gid=`.
+	pub gid: Gid,
+}
+
+impl Default for HidepidPolicy {
+	fn default() -> Self {
+		Self { level: 0, gid: 0 }
+	}
+}
+
+impl HidepidPolicy {
+	/// Parses `hidepid=`/`gid=` tokens out of a comma-separated mount option string.
+	///
+	/// Unknown tokens are ignored, the same as a real mount(8) tolerates options it doesn't
+	/// recognize.
+	pub fn parse(options: &str) -> Self {
+		let mut policy = Self::default();
+
+		for token in options.split(',') {
+			if let Some(value) = token.strip_prefix("hidepid=") {
+				if let Ok(level) = value.parse::<u8>() {
+					policy.level = level.min(2);
+				}
+			} else if let Some(value) = token.strip_prefix("gid=") {
+				if let Ok(gid) = value.parse::<Gid>() {
+					policy.gid = gid;
+				}
+			}
+		}
+
+		policy
+	}
+
+	/// Tells whether the currently running process may look up or read a `/proc/[pid]` directory
+	/// owned by `owner`.
+	///
+	/// At `level` `0`, every caller may. At `level` `1` or `2`, only the owner, `root`, and a
+	/// caller whose effective group is [`Self::gid`] may.
+	pub fn can_access(&self, owner: Uid) -> bool {
+		if self.level == 0 {
+			return true;
+		}
+
+		let Some(proc_mutex) = Process::get_current() else {
+			return true;
+		};
+		let proc_guard = proc_mutex.lock();
+		let proc = proc_guard.get();
+
+		proc.get_euid() == 0 || proc.get_euid() == owner || proc.get_egid() == self.gid
+	}
+
+	/// Tells whether a `/proc/[pid]` directory owned by `owner` should be omitted when the
+	/// currently running process enumerates the procfs root.
+	pub fn should_hide_from_listing(&self, owner: Uid) -> bool {
+		self.level >= 2 && !self.can_access(owner)
+	}
+}
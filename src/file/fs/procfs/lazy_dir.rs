@@ -0,0 +1,78 @@
+//! Lazy, on-demand resolution of `/proc/[pid]` directories for [`super::ProcFS`], looked up
+//! straight from [`process::get_scheduler`] instead of the eager `add_process`/`remove_process`
+//! bookkeeping it replaces.
+
+use crate::file::INode;
+use crate::process;
+use crate::process::pid::Pid;
+use crate::util::container::hashmap::HashMap;
+
+/// The maximum number of `Pid -> INode` pairs kept in [`LazyProcCache`].
+///
+/// This is purely a performance cache: entries are evicted by recency, never for correctness, so
+/// a small bound is enough to keep a hot `/proc/[pid]` from being rebuilt on every syscall without
+/// growing unbounded as processes come and go.
+const CACHE_CAPACITY: usize = 32;
+
+/// A small, bounded, least-recently-used cache from a [`Pid`] to the inode of its already
+/// materialized `/proc/[pid]` node.
+///
+/// The cache exists only to avoid rebuilding a node on every lookup; the scheduler's process
+/// table, not this cache, is the source of truth for whether a PID is still alive.
+#[derive(Debug, Default)]
+pub struct LazyProcCache {
+	/// The cached entries, along with a recency counter used to pick an eviction victim.
+	entries: HashMap<Pid, (INode, u64)>,
+	/// Monotonically increasing counter, bumped on every access, used to timestamp entries.
+	clock: u64,
+}
+
+impl LazyProcCache {
+	/// Creates a new, empty cache.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Returns the cached inode for `pid`, if any, marking it as the most recently used.
+	pub fn get(&mut self, pid: Pid) -> Option<INode> {
+		self.clock += 1;
+		let clock = self.clock;
+		let entry = self.entries.get_mut(&pid)?;
+		entry.1 = clock;
+		Some(entry.0)
+	}
+
+	/// Inserts `inode` as the cached node for `pid`, evicting the least recently used entry first
+	/// if the cache is already at [`CACHE_CAPACITY`].
+	pub fn insert(&mut self, pid: Pid, inode: INode) {
+		self.clock += 1;
+
+		if self.entries.len() >= CACHE_CAPACITY && !self.entries.contains_key(&pid) {
+			if let Some((&victim, _)) = self.entries.iter().min_by_key(|(_, (_, seen))| *seen) {
+				let _ = self.entries.remove(&victim);
+			}
+		}
+
+		let _ = self.entries.insert(pid, (inode, self.clock));
+	}
+
+	/// Drops the cached entry for `pid`, if any, so a later lookup rebuilds it from the live
+	/// process table.
+	pub fn invalidate(&mut self, pid: Pid) {
+		let _ = self.entries.remove(&pid);
+	}
+}
+
+/// Tells whether `pid` still names a live process, i.e. whether a `/proc/[pid]` lookup for it
+/// should succeed.
+pub fn is_alive(pid: Pid) -> bool {
+	process::get_scheduler().lock().get_process(pid).is_some()
+}
+
+/// Parses `name` as a `/proc/[pid]` entry name, returning the corresponding [`Pid`] if it is one.
+///
+/// A name that fails to parse as a `Pid` isn't a process directory at all, and the caller should
+/// fall through to its other, statically named entries (`meminfo`, `self`, ...).
+pub fn parse_pid(name: &[u8]) -> Option<Pid> {
+	core::str::from_utf8(name).ok()?.parse().ok()
+}
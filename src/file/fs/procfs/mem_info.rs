@@ -47,7 +47,7 @@ impl IO for MemInfo {
 		Ok((len as _, eof))
 	}
 
-	fn write(&mut self, _offset: u64, _buff: &[u8]) -> Result<u64, Errno> {
+	fn write(&mut self, _offset: u64, _buff: &[u8], _nonblock: bool) -> Result<u64, Errno> {
 		Err(errno!(EINVAL))
 	}
 
@@ -1,20 +1,25 @@
 //! The procfs is a virtual filesystem which provides informations about
 //! processes.
 
+mod fd_dir;
+mod hidepid;
+mod lazy_dir;
 mod mem_info;
 mod proc_dir;
 mod self_link;
+pub mod stat;
+mod subset;
 mod sys_dir;
+pub mod sysctl;
 mod uptime;
 mod version;
 
 use super::kernfs;
 use super::kernfs::node::DummyKernFSNode;
+use super::kernfs::node::KernFSNode;
 use super::kernfs::KernFS;
 use super::Filesystem;
 use super::FilesystemType;
-use crate::errno::AllocError;
-use crate::errno::AllocResult;
 use crate::errno::Errno;
 use crate::file::fs::Statfs;
 use crate::file::path::Path;
@@ -27,7 +32,6 @@ use crate::file::FileType;
 use crate::file::INode;
 use crate::file::Mode;
 use crate::process;
-use crate::process::oom;
 use crate::process::pid::Pid;
 use crate::util::boxed::Box;
 use crate::util::container::hashmap::HashMap;
@@ -35,58 +39,132 @@ use crate::util::container::string::String;
 use crate::util::io::IO;
 use crate::util::lock::Mutex;
 use crate::util::ptr::arc::Arc;
+use crate::util::ptr::cow::Cow;
 use core::any::Any;
+use fd_dir::FdDir;
+use hidepid::HidepidPolicy;
+use lazy_dir::LazyProcCache;
 use mem_info::MemInfo;
 use proc_dir::ProcDir;
 use self_link::SelfNode;
+use subset::SubsetPolicy;
 use sys_dir::SysDir;
 use uptime::Uptime;
 use version::Version;
 
+/// The procfs root directory, `/proc` itself.
+///
+/// Its non-PID entries (`meminfo`, `self`, `sys`, ...) are built once at mount time, but unlike
+/// those, its per-PID entries are never stored: they are rebuilt from the live process table on
+/// every [`Self::get_content`] call, the same rebuild-on-read tradeoff [`sys_dir::SysDir`] and
+/// [`fd_dir::FdDir`] make, and filtered by [`HidepidPolicy::should_hide_from_listing`] for
+/// whichever process is calling *this* read, so a `hidepid=2` mount never bakes one reader's
+/// visibility into what every other reader sees afterwards.
+#[derive(Debug)]
+struct ProcRoot {
+	/// The non-PID entries, built once by [`ProcFS::new`].
+	static_entries: HashMap<String, DirEntry>,
+	/// The `hidepid=`/`gid=` visibility policy this mount was given.
+	hidepid: HidepidPolicy,
+}
+
+impl KernFSNode for ProcRoot {
+	fn get_mode(&self) -> Mode {
+		0o555
+	}
+
+	fn get_content<'a>(&'a self) -> Cow<'a, FileContent> {
+		let mut entries = HashMap::new();
+		for (name, entry) in self.static_entries.iter() {
+			let _ = entries.insert(name.clone(), entry.clone());
+		}
+
+		let scheduler = process::get_scheduler().lock();
+		for (pid, proc_mutex) in scheduler.iter_process() {
+			let owner = proc_mutex.lock().get_euid();
+			if self.hidepid.should_hide_from_listing(owner) {
+				continue;
+			}
+
+			let Ok(name) = crate::format!("{pid}") else {
+				continue;
+			};
+			let _ = entries.insert(
+				name,
+				DirEntry {
+					// A placeholder, non-kernfs inode, the same tradeoff `FdDir::get_content`
+					// makes for fd entries: opening the entry goes through `ProcFS::get_inode`,
+					// which materializes the real node, rather than through this value.
+					inode: *pid as _,
+					entry_type: FileType::Directory,
+				},
+			);
+		}
+
+		FileContent::Directory(entries).into()
+	}
+}
+
 /// Structure representing the procfs.
 ///
 /// On the inside, the procfs works using a kernfs.
 pub struct ProcFS {
 	/// The kernfs.
 	fs: KernFS,
-	/// The list of registered processes with their directory's inode.
-	procs: HashMap<Pid, INode>,
+	/// Cache of already materialized `/proc/[pid]` directories. The live process table, not this
+	/// cache, is the source of truth for which PIDs exist.
+	cache: LazyProcCache,
+	/// The `hidepid=`/`gid=` visibility policy this mount was given.
+	hidepid: HidepidPolicy,
+	/// The `subset=pid` restriction this mount was given.
+	subset: SubsetPolicy,
 }
 
 impl ProcFS {
 	/// Creates a new instance.
 	///
-	/// `readonly` tells whether the filesystem is readonly.
-	pub fn new(readonly: bool) -> Result<Self, Errno> {
+	/// `readonly` tells whether the filesystem is readonly. `options` is the raw, comma-separated
+	/// mount option string, from which `hidepid=`/`gid=`/`subset=pid` are parsed; unknown tokens
+	/// are ignored.
+	pub fn new(readonly: bool, options: &str) -> Result<Self, Errno> {
+		let hidepid = HidepidPolicy::parse(options);
+		let subset = SubsetPolicy::parse(options);
+
 		let mut fs = Self {
 			fs: KernFS::new(b"procfs".try_into()?, readonly)?,
-			procs: HashMap::new(),
+			cache: LazyProcCache::new(),
+			hidepid,
+			subset,
 		};
 
 		let mut entries = HashMap::new();
 
-		// Create /proc/meminfo
-		let node = MemInfo {};
-		let inode = fs.fs.add_node(Box::new(node)?)?;
-		entries.insert(
-			b"meminfo".try_into()?,
-			DirEntry {
-				inode,
-				entry_type: FileType::Regular,
-			},
-		)?;
+		if !subset.is_hidden(b"meminfo") {
+			// Create /proc/meminfo
+			let node = MemInfo {};
+			let inode = fs.fs.add_node(Box::new(node)?)?;
+			entries.insert(
+				b"meminfo".try_into()?,
+				DirEntry {
+					inode,
+					entry_type: FileType::Regular,
+				},
+			)?;
+		}
 
-		// Create /proc/mounts
-		let node =
-			DummyKernFSNode::new(0o777, 0, 0, FileContent::Link(b"self/mounts".try_into()?));
-		let inode = fs.fs.add_node(Box::new(node)?)?;
-		entries.insert(
-			b"mounts".try_into()?,
-			DirEntry {
-				inode,
-				entry_type: FileType::Link,
-			},
-		)?;
+		if !subset.is_hidden(b"mounts") {
+			// Create /proc/mounts
+			let node =
+				DummyKernFSNode::new(0o777, 0, 0, FileContent::Link(b"self/mounts".try_into()?));
+			let inode = fs.fs.add_node(Box::new(node)?)?;
+			entries.insert(
+				b"mounts".try_into()?,
+				DirEntry {
+					inode,
+					entry_type: FileType::Link,
+				},
+			)?;
+		}
 
 		// Create /proc/self
 		let node = SelfNode {};
@@ -99,108 +177,131 @@ impl ProcFS {
 			},
 		)?;
 
-		// Create /proc/sys
-		let node = SysDir::new(&mut fs.fs)?;
-		let inode = fs.fs.add_node(Box::new(node)?)?;
-		entries.insert(
-			b"sys".try_into()?,
-			DirEntry {
-				inode,
-				entry_type: FileType::Directory,
-			},
-		)?;
+		if !subset.is_hidden(b"sys") {
+			// Create /proc/sys
+			let node = SysDir::new(&mut fs.fs)?;
+			let inode = fs.fs.add_node(Box::new(node)?)?;
+			entries.insert(
+				b"sys".try_into()?,
+				DirEntry {
+					inode,
+					entry_type: FileType::Directory,
+				},
+			)?;
+		}
 
-		// Create /proc/uptime
-		let node = Uptime {};
-		let inode = fs.fs.add_node(Box::new(node)?)?;
-		entries.insert(
-			b"uptime".try_into()?,
-			DirEntry {
-				inode,
-				entry_type: FileType::Regular,
-			},
-		)?;
+		if !subset.is_hidden(b"uptime") {
+			// Create /proc/uptime
+			let node = Uptime {};
+			let inode = fs.fs.add_node(Box::new(node)?)?;
+			entries.insert(
+				b"uptime".try_into()?,
+				DirEntry {
+					inode,
+					entry_type: FileType::Regular,
+				},
+			)?;
+		}
 
-		// Create /proc/version
-		let node = Version {};
-		let inode = fs.fs.add_node(Box::new(node)?)?;
-		entries.insert(
-			b"version".try_into()?,
-			DirEntry {
-				inode,
-				entry_type: FileType::Regular,
-			},
-		)?;
+		if !subset.is_hidden(b"version") {
+			// Create /proc/version
+			let node = Version {};
+			let inode = fs.fs.add_node(Box::new(node)?)?;
+			entries.insert(
+				b"version".try_into()?,
+				DirEntry {
+					inode,
+					entry_type: FileType::Regular,
+				},
+			)?;
+		}
 
-		// Add the root node
-		let root_node = DummyKernFSNode::new(0o555, 0, 0, FileContent::Directory(entries));
+		// Add the root node. Its per-PID entries are never built here: `ProcRoot::get_content`
+		// enumerates the live process table fresh on every read.
+		let root_node = ProcRoot {
+			static_entries: entries,
+			hidepid,
+		};
 		fs.fs.set_root(Box::new(root_node)?)?;
 
-		// Add existing processes
-		{
-			let mut scheduler = process::get_scheduler().lock();
-			for (pid, _) in scheduler.iter_process() {
-				fs.add_process(*pid)?;
-			}
-		}
-
 		Ok(fs)
 	}
 
-	/// Adds a process with the given PID `pid` to the filesystem.
-	pub fn add_process(&mut self, pid: Pid) -> Result<(), Errno> {
-		// Create the process's node
+	/// Materializes (creating its `ProcDir` node on first access) or returns the already-cached
+	/// inode for `/proc/[pid]`, validated against the live process table on every call rather than
+	/// against any previously cached state.
+	///
+	/// Returns `None` if `pid` doesn't currently name a live process, or if the currently running
+	/// process isn't allowed to see it under `hidepid=`/`gid=`.
+	fn resolve_proc_inode(&mut self, pid: Pid) -> Result<Option<INode>, Errno> {
+		if !lazy_dir::is_alive(pid) {
+			self.cache.invalidate(pid);
+			return Ok(None);
+		}
+
+		let owner = process::get_scheduler()
+			.lock()
+			.get_process(pid)
+			.map(|proc_mutex| proc_mutex.lock().get_euid())
+			.unwrap_or(0);
+		if !self.hidepid.can_access(owner) {
+			return Ok(None);
+		}
+
+		if let Some(inode) = self.cache.get(pid) {
+			return Ok(Some(inode));
+		}
+
 		let proc_node = ProcDir::new(pid, &mut self.fs)?;
 		let inode = self.fs.add_node(Box::new(proc_node)?)?;
-		oom::wrap(|| self.procs.insert(pid, inode));
-
-		// Insert the process's entry at the root of the filesystem
-		let root = self.fs.get_node_mut(kernfs::ROOT_INODE).unwrap();
-		oom::wrap(|| {
-			let mut content = root.get_content().map_err(|_| AllocError)?;
-			let FileContent::Directory(entries) = &mut *content else {
-				unreachable!();
-			};
-			entries.insert(
-				crate::format!("{pid}")?,
-				DirEntry {
-					entry_type: FileType::Directory,
-					inode,
-				},
-			)
-		});
+		self.cache.insert(pid, inode);
 
-		Ok(())
+		Ok(Some(inode))
 	}
 
-	/// Removes the process with pid `pid` from the filesystem.
-	///
-	/// If the process doesn't exist, the function does nothing.
-	pub fn remove_process(&mut self, pid: Pid) -> AllocResult<()> {
-		let Some(inode) = self.procs.remove(&pid) else {
-			return Ok(());
+	/// Tells whether the currently running process may look up the root-level entry `name`,
+	/// enforcing `subset=pid` and, for a numeric (per-PID) entry, materializing its `/proc/[pid]`
+	/// node on demand via [`Self::resolve_proc_inode`].
+	fn can_lookup_root_entry(&mut self, name: &[u8]) -> Result<bool, Errno> {
+		if self.subset.is_hidden(name) {
+			return Ok(false);
+		}
+
+		let Some(pid) = lazy_dir::parse_pid(name) else {
+			return Ok(true);
 		};
 
-		// Remove the process's entry from the root of the filesystem
-		let root = self.fs.get_node_mut(kernfs::ROOT_INODE).unwrap();
-		oom::wrap(|| {
-			let mut content = root.get_content().map_err(|_| AllocError)?;
-			let FileContent::Directory(entries) = &mut *content else {
-				unreachable!();
-			};
-			entries.remove(&crate::format!("{pid}")?);
-			Ok(())
-		});
+		Ok(self.resolve_proc_inode(pid)?.is_some())
+	}
 
-		// Remove the node
-		if let Some(mut node) = oom::wrap(|| self.fs.remove_node(inode).map_err(|_| AllocError)) {
-			let node = node.as_mut() as &mut dyn Any;
+	/// If `parent` is a [`FdDir`] node and `name` parses as one of its open file descriptor
+	/// numbers, materializes (and returns the inode of) a fresh link node pointing at that
+	/// descriptor's currently open file.
+	///
+	/// Returns `Ok(None)` if `parent` isn't a [`FdDir`], so the caller falls through to its other
+	/// resolution paths; fails with `ENOENT` if `parent` is a `FdDir` but `name` doesn't name one
+	/// of its currently open descriptors.
+	///
+	/// A link node is built fresh on every lookup rather than cached: a fd can be closed and reused
+	/// for an unrelated file at any time, which would leave a cached target stale.
+	fn resolve_fd_link(&mut self, parent: INode, name: &[u8]) -> Result<Option<INode>, Errno> {
+		let Some(pid) = self
+			.fs
+			.get_node(parent)
+			.and_then(|node| (node as &dyn Any).downcast_ref::<FdDir>())
+			.map(FdDir::pid)
+		else {
+			return Ok(None);
+		};
 
-			if let Some(node) = node.downcast_mut::<ProcDir>() {
-				node.drop_inner(&mut self.fs);
-			}
-		}
-		Ok(())
+		let fd_num = core::str::from_utf8(name)
+			.ok()
+			.and_then(|s| s.parse::<u32>().ok())
+			.ok_or_else(|| errno!(ENOENT))?;
+		let target = fd_dir::resolve_target(pid, fd_num).ok_or_else(|| errno!(ENOENT))?;
+
+		let node = DummyKernFSNode::new(0o777, 0, 0, FileContent::Link(target));
+		Ok(Some(self.fs.add_node(Box::new(node)?)?))
 	}
 }
 
@@ -218,7 +319,9 @@ impl Filesystem for ProcFS {
 	}
 
 	fn get_stat(&self, io: &mut dyn IO) -> Result<Statfs, Errno> {
-		self.fs.get_stat(io)
+		let base = self.fs.get_stat(io)?;
+		let process_count = process::get_scheduler().lock().iter_process().count();
+		Ok(stat::build(base, process_count))
 	}
 
 	fn get_root_inode(&self, io: &mut dyn IO) -> Result<INode, Errno> {
@@ -231,10 +334,26 @@ impl Filesystem for ProcFS {
 		parent: Option<INode>,
 		name: &[u8],
 	) -> Result<INode, Errno> {
+		if parent == Some(kernfs::ROOT_INODE) && !self.can_lookup_root_entry(name)? {
+			return Err(errno!(ENOENT));
+		}
+		if let Some(parent_inode) = parent {
+			if let Some(inode) = self.resolve_fd_link(parent_inode, name)? {
+				return Ok(inode);
+			}
+		}
+
 		self.fs.get_inode(io, parent, name)
 	}
 
 	fn load_file(&mut self, io: &mut dyn IO, inode: INode, name: String) -> Result<File, Errno> {
+		if inode == kernfs::ROOT_INODE && !self.can_lookup_root_entry(&name)? {
+			return Err(errno!(ENOENT));
+		}
+		if let Some(fd_inode) = self.resolve_fd_link(inode, &name)? {
+			return self.fs.load_file(io, fd_inode, name);
+		}
+
 		self.fs.load_file(io, inode, name)
 	}
 
@@ -312,7 +431,11 @@ impl FilesystemType for ProcFsType {
 		_io: &mut dyn IO,
 		_mountpath: Path,
 		readonly: bool,
+		options: &[u8],
 	) -> Result<Arc<Mutex<dyn Filesystem>>, Errno> {
-		Ok(Arc::new(Mutex::new(ProcFS::new(readonly)?))?)
+		// Unrecognized option bytes (or a non-UTF8 string, which a real mount option string never
+		// is) are tolerated the same way an unrecognized token within the string already is.
+		let options = core::str::from_utf8(options).unwrap_or("");
+		Ok(Arc::new(Mutex::new(ProcFS::new(readonly, options)?))?)
 	}
 }
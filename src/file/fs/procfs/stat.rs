@@ -0,0 +1,22 @@
+//! Overrides [`super::ProcFS::get_stat`] to report [`PROC_SUPER_MAGIC`] instead of forwarding to
+//! the backing [`super::kernfs::KernFS`], so `fstatfs`-based procfs detection works.
+
+use crate::file::fs::Statfs;
+
+/// The magic number reported by `statfs`/`fstatfs` for a procfs mount, matching Linux's
+/// `PROC_SUPER_MAGIC`.
+pub const PROC_SUPER_MAGIC: u32 = 0x9fa0;
+
+/// Builds the [`Statfs`] reported for a [`super::ProcFS`] instance with `process_count` currently
+/// registered processes.
+///
+/// procfs has no backing blocks, so the block-related fields are left at whatever `base` (the
+/// backing [`super::kernfs::KernFS`]'s own [`Statfs`]) already set them to; only the magic number
+/// and the inode/file count, which are meaningful for a virtual filesystem, are overridden.
+pub fn build(base: Statfs, process_count: usize) -> Statfs {
+	Statfs {
+		f_type: PROC_SUPER_MAGIC,
+		f_files: process_count as _,
+		..base
+	}
+}
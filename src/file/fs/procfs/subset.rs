@@ -0,0 +1,33 @@
+//! `subset=pid` mount-option parsing and enforcement for [`super::ProcFS`], restricting a mount
+//! to `self` and the per-PID directories only.
+
+/// The names of the root-level entries hidden by `subset=pid`, in addition to whatever per-PID
+/// directories [`super::ProcFS`] exposes.
+const HIDDEN_ENTRIES: [&[u8]; 5] = [b"meminfo", b"mounts", b"uptime", b"version", b"sys"];
+
+/// Whether a `ProcFS` mount is restricted to `self` and `/proc/[pid]` directories, as configured
+/// by the `subset=pid` mount option.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SubsetPolicy {
+	/// `true` if `subset=pid` was given.
+	pid_only: bool,
+}
+
+impl SubsetPolicy {
+	/// Parses the `subset=pid` token out of a comma-separated mount option string.
+	///
+	/// Unknown tokens are ignored, the same as [`super::hidepid::HidepidPolicy::parse`].
+	pub fn parse(options: &str) -> Self {
+		Self {
+			pid_only: options.split(',').any(|token| token == "subset=pid"),
+		}
+	}
+
+	/// Tells whether `name`, a root-level entry name, is hidden by this policy.
+	///
+	/// A caller holding a stale inode obtained before the mount was reconfigured is still denied:
+	/// this is meant to be consulted on every lookup, not only when building the root listing.
+	pub fn is_hidden(&self, name: &[u8]) -> bool {
+		self.pid_only && HIDDEN_ENTRIES.contains(&name)
+	}
+}
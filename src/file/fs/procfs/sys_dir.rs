@@ -0,0 +1,147 @@
+//! `/proc/sys`, the writable sysctl tree.
+//!
+//! Unlike most other procfs entries, this directory exposes no fixed set of files: each tunable
+//! is added by [`SysDir::register`], called by whichever kernel subsystem owns it (a `Hostname`
+//! entry registered by the hostname code, a `PidMax` entry registered by the PID allocator, ...),
+//! the same `register`-at-init pattern already used by `device::register_device`, rather than
+//! this module hard-coding every tunable it exposes.
+
+use super::sysctl::SysctlEntry;
+use super::sysctl::SysctlNode;
+use crate::errno::Errno;
+use crate::file::fs::kernfs::node::KernFSNode;
+use crate::file::fs::kernfs::KernFS;
+use crate::file::DirEntry;
+use crate::file::FileContent;
+use crate::file::FileType;
+use crate::file::INode;
+use crate::file::Mode;
+use crate::util::boxed::Box;
+use crate::util::container::hashmap::HashMap;
+use crate::util::container::string::String;
+use crate::util::ptr::cow::Cow;
+use core::any::Any;
+
+/// The `/proc/sys` directory node.
+///
+/// A `SysDir` may itself hold other `SysDir`s as children (e.g. `/proc/sys/kernel`), each its own
+/// node in `fs`, rather than flattening the whole tree into one node: that keeps every directory
+/// along the way independently listable, the same as any other `kernfs` directory.
+#[derive(Debug, Default)]
+pub struct SysDir {
+	/// The directory's current children, keyed by name.
+	entries: HashMap<String, DirEntry>,
+}
+
+impl SysDir {
+	/// Creates a new, empty `/proc/sys` directory.
+	pub fn new(_fs: &mut KernFS) -> Result<Self, Errno> {
+		Ok(Self::default())
+	}
+
+	/// Registers `entry` as the tunable exposed at `/proc/sys/<path>`, creating its backing node in
+	/// `fs` along with any intermediate directory named in `path` that doesn't already exist (e.g.
+	/// `kernel/hostname` creates a `kernel` `SysDir` first if needed, then `hostname` inside it).
+	///
+	/// `path` must hold at least one component.
+	pub fn register(
+		&mut self,
+		fs: &mut KernFS,
+		path: &[&[u8]],
+		entry: Box<dyn SysctlEntry>,
+	) -> Result<(), Errno> {
+		let Some((leaf, dirs)) = path.split_last() else {
+			return Err(errno!(EINVAL));
+		};
+
+		// Walk down `dirs`, creating any missing intermediate `SysDir`, tracking the inode of the
+		// directory reached so far (`None` meaning `self`, the `/proc/sys` root itself).
+		let mut current = None;
+		for &component in dirs {
+			let name: String = component.try_into()?;
+			current = Some(self.child_dir(fs, current, name)?);
+		}
+
+		let inode = fs.add_node(Box::new(SysctlNode::new(entry))?)?;
+		self.insert_entry(
+			fs,
+			current,
+			leaf.try_into()?,
+			DirEntry {
+				inode,
+				entry_type: FileType::Regular,
+			},
+		)
+	}
+
+	/// Returns the inode of the child `SysDir` named `name` directly under `at` (or under `self`
+	/// if `at` is `None`), creating it first if it doesn't already exist.
+	fn child_dir(&mut self, fs: &mut KernFS, at: Option<INode>, name: String) -> Result<INode, Errno> {
+		let existing = match at {
+			None => self.entries.get(&name).map(|entry| entry.inode),
+			Some(inode) => fs
+				.get_node(inode)
+				.and_then(|node| (node as &dyn Any).downcast_ref::<SysDir>())
+				.and_then(|dir| dir.entries.get(&name))
+				.map(|entry| entry.inode),
+		};
+		if let Some(inode) = existing {
+			return Ok(inode);
+		}
+
+		let inode = fs.add_node(Box::new(SysDir::new(fs)?)?)?;
+		self.insert_entry(
+			fs,
+			at,
+			name,
+			DirEntry {
+				inode,
+				entry_type: FileType::Directory,
+			},
+		)?;
+
+		Ok(inode)
+	}
+
+	/// Inserts `entry` under name `name` into the directory `at` (or `self` if `at` is `None`).
+	fn insert_entry(
+		&mut self,
+		fs: &mut KernFS,
+		at: Option<INode>,
+		name: String,
+		entry: DirEntry,
+	) -> Result<(), Errno> {
+		match at {
+			None => {
+				self.entries.insert(name, entry)?;
+			}
+			Some(inode) => {
+				let node = fs.get_node_mut(inode).ok_or_else(|| errno!(ENOENT))?;
+				let dir = (node as &mut dyn Any)
+					.downcast_mut::<SysDir>()
+					.ok_or_else(|| errno!(ENOENT))?;
+				dir.entries.insert(name, entry)?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+impl KernFSNode for SysDir {
+	fn get_mode(&self) -> Mode {
+		0o555
+	}
+
+	fn get_content<'a>(&'a self) -> Cow<'a, FileContent> {
+		// Rebuilt from `entries` on every call rather than cached as a `FileContent` field, the
+		// same tradeoff `FdDir::get_content` makes, so a registration made between two reads is
+		// never missed.
+		let mut out = HashMap::new();
+		for (name, entry) in self.entries.iter() {
+			let _ = out.insert(name.clone(), entry.clone());
+		}
+
+		FileContent::Directory(out).into()
+	}
+}
@@ -0,0 +1,80 @@
+//! Turns [`super::sys_dir::SysDir`]'s `/proc/sys` tree into a registration API for live,
+//! writable kernel tunables, rather than a set of entries it hard-codes itself.
+
+use crate::errno::Errno;
+use crate::file::fs::kernfs::node::KernFSNode;
+use crate::file::FileContent;
+use crate::file::Mode;
+use crate::util::boxed::Box;
+use crate::util::io::IO;
+use crate::util::ptr::cow::Cow;
+use core::cmp::min;
+
+/// A single writable kernel tunable exposed under `/proc/sys`.
+///
+/// A kernel subsystem implements this once per tunable (e.g. a `Hostname` entry backed by the
+/// kernel's hostname global, a `PidMax` entry backed by the PID allocator's ceiling) and registers
+/// it with [`super::sys_dir::SysDir::register`] at init, the same way `device::register_device`
+/// is called from each driver's `init`, rather than `SysDir` hard-coding every tunable it exposes.
+pub trait SysctlEntry {
+	/// Formats the tunable's current value into `buf`, returning the number of bytes written.
+	fn read(&self, buf: &mut [u8]) -> usize;
+
+	/// Parses `buf` and applies it as the tunable's new value.
+	fn write(&mut self, buf: &[u8]) -> Result<(), Errno>;
+}
+
+/// A [`KernFSNode`] wrapping a [`SysctlEntry`], letting `read_node`/`write_node` on the backing
+/// `KernFS` reach it without any change to `ProcFS` itself: `echo value > /proc/sys/kernel/
+/// hostname` ends up calling the wrapped entry's [`SysctlEntry::write`].
+pub struct SysctlNode {
+	/// The wrapped tunable.
+	entry: Box<dyn SysctlEntry>,
+}
+
+impl SysctlNode {
+	/// Creates a new leaf node wrapping `entry`.
+	pub fn new(entry: Box<dyn SysctlEntry>) -> Self {
+		Self { entry }
+	}
+}
+
+impl KernFSNode for SysctlNode {
+	fn get_mode(&self) -> Mode {
+		0o644
+	}
+
+	fn get_content<'a>(&'a self) -> Cow<'a, FileContent> {
+		FileContent::Regular.into()
+	}
+}
+
+impl IO for SysctlNode {
+	fn get_size(&self) -> u64 {
+		0
+	}
+
+	fn read(&mut self, offset: u64, buf: &mut [u8]) -> Result<(u64, bool), Errno> {
+		let mut tmp = [0u8; 256];
+		let len = self.entry.read(&mut tmp);
+
+		if offset >= len as u64 {
+			return Ok((0, true));
+		}
+
+		let off = offset as usize;
+		let n = min(len - off, buf.len());
+		buf[..n].copy_from_slice(&tmp[off..off + n]);
+
+		Ok((n as _, (offset + n as u64) >= len as u64))
+	}
+
+	fn write(&mut self, _offset: u64, buf: &[u8], _nonblock: bool) -> Result<u64, Errno> {
+		self.entry.write(buf)?;
+		Ok(buf.len() as _)
+	}
+
+	fn poll(&mut self, _mask: u32) -> Result<u32, Errno> {
+		Ok(0)
+	}
+}
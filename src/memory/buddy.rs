@@ -174,9 +174,31 @@ pub fn init() {
 	// TODO
 	z[2].lock().get_mut().init(FLAG_ZONE_TYPE_DMA, 0 as *mut Void, 0, 0 as *mut Void);
 	z[2].unlock();
+
+	// Carve a region out of whatever physical memory is left above the kernel zone and hand it to
+	// the CMA, which manages it with its own page-granular bitmap instead of the buddy free lists.
+	let cma_begin = kernel_zone_end;
+	let cma_end = util::down_align(mmap_info.phys_alloc_end, memory::PAGE_SIZE);
+	if (cma_end as usize) > (cma_begin as usize) {
+		let cma_size = cma_end as usize - cma_begin as usize;
+		crate::memory::cma::init(cma_begin, cma_size);
+	}
+}
+
+/*
+ * Returns the fallback-ordered list of zone types to try for an allocation requesting zone type
+ * `type_`, from most to least preferred. Each zone type falls back to the next one down in
+ * priority (USER to KERNEL to DMA), except `FLAG_ZONE_TYPE_DMA` itself, which must never be
+ * satisfied by another zone since the caller specifically needs DMA-capable memory.
+ */
+fn get_zonelist(type_: Flags) -> &'static [Flags] {
+	match type_ {
+		FLAG_ZONE_TYPE_USER => &[FLAG_ZONE_TYPE_USER, FLAG_ZONE_TYPE_KERNEL, FLAG_ZONE_TYPE_DMA],
+		FLAG_ZONE_TYPE_KERNEL => &[FLAG_ZONE_TYPE_KERNEL, FLAG_ZONE_TYPE_DMA],
+		_ => &[FLAG_ZONE_TYPE_DMA],
+	}
 }
 
-// TODO Allow to fallback to another zone if the one that is returned is full
 /*
  * Returns a mutable reference to a zone suitable for an allocation with the given type `type_`.
  */
@@ -218,26 +240,35 @@ fn get_zone_for_pointer(ptr: *const Void) -> Option<&'static mut Mutex<Zone>> {
 /*
  * Allocates a frame of memory using the buddy allocator. `order` is the order of the frame to be
  * allocated.
+ *
+ * If the zone matching `flags`'s zone type is full, the zones of `get_zonelist` are tried in
+ * turn so that, for instance, a KERNEL request can be served from the DMA zone rather than
+ * failing outright. `free` and `get_zone_for_pointer` locate a frame by its physical address, so
+ * a frame coming from a fallback zone is freed correctly regardless of the zone type originally
+ * requested.
  * TODO document flags
  */
 pub fn alloc(order: FrameOrder, flags: Flags) -> Result<*mut Void, ()> {
 	debug_assert!(order <= MAX_ORDER);
 
-	let z = get_suitable_zone(flags & ZONE_TYPE_MASK);
-	if let Some(z_) = z {
-		let mut guard = MutexGuard::new(z_);
-		let zone = guard.get_mut();
-
-		let frame = zone.get_available_frame(order);
-		if let Some(f) = frame {
-			f.split(zone, order);
-			f.mark_used();
-			zone.allocated_pages += util::pow2(order as _) as usize;
-
-			let ptr = f.get_ptr(zone);
-			debug_assert!(util::is_aligned(ptr, memory::PAGE_SIZE));
-			debug_assert!(ptr >= zone.begin && ptr < (zone.begin as usize + zone.get_size()) as _);
-			return Ok(ptr);
+	for &type_ in get_zonelist(flags & ZONE_TYPE_MASK) {
+		let z = get_suitable_zone(type_);
+		if let Some(z_) = z {
+			let mut guard = MutexGuard::new(z_);
+			let zone = guard.get_mut();
+
+			let frame = zone.get_available_frame(order);
+			if let Some(f) = frame {
+				f.split(zone, order);
+				f.mark_used();
+				zone.allocated_pages += util::pow2(order as _) as usize;
+
+				let ptr = f.get_ptr(zone);
+				debug_assert!(util::is_aligned(ptr, memory::PAGE_SIZE));
+				debug_assert!(ptr >= zone.begin
+					&& ptr < (zone.begin as usize + zone.get_size()) as _);
+				return Ok(ptr);
+			}
 		}
 	}
 	Err(())
@@ -283,6 +314,92 @@ pub fn free_kernel(ptr: *const Void, order: FrameOrder) {
 	free(memory::kern_to_phys(ptr), order);
 }
 
+/*
+ * Reserves a single frame of order `order` located at physical address `ptr`, marking it
+ * permanently used so it can never be handed out by `alloc`. The frame must currently be free,
+ * possibly as part of a larger coalesced free block, in which case the surrounding block is
+ * split down around `ptr` until a frame of exactly `order` remains; every other half produced
+ * while splitting is linked back into the free list.
+ *
+ * This is meant to be called during `init`, right after `fill_free_list`, to carve out regions
+ * that are already in use by something else (the framebuffer, the ACPI tables discovered by
+ * `Rsdt::foreach_table`, multiboot modules, etc) before the rest of the kernel starts allocating
+ * memory. If `ptr` doesn't belong to any zone or the covering frame is already used, the function
+ * does nothing.
+ */
+pub fn reserve(ptr: *const Void, order: FrameOrder) {
+	debug_assert!(util::is_aligned(ptr, memory::PAGE_SIZE));
+	debug_assert!(order <= MAX_ORDER);
+
+	let z = get_zone_for_pointer(ptr);
+	if let Some(z_) = z {
+		let mut guard = MutexGuard::new(z_);
+		let zone = guard.get_mut();
+
+		let frame_id = zone.get_frame_id_from_ptr(ptr);
+		debug_assert!(frame_id < zone.get_pages_count());
+
+		if let Some(head) = zone.find_free_block(frame_id) {
+			let frame = head.split_at(zone, frame_id, order);
+			frame.mark_used();
+			zone.allocated_pages += util::pow2(order as _) as usize;
+		}
+	}
+}
+
+/*
+ * Reserves every frame covering the physical range [`phys_begin`, `phys_end`), rounding the
+ * range outward to page boundaries. Frames already marked used are left untouched; free frames
+ * straddling the range are split down to order `0` so that only the pages actually inside the
+ * range get reserved, the rest of the block they belonged to staying available.
+ */
+pub fn reserve_range(phys_begin: *const Void, phys_end: *const Void) {
+	debug_assert!(phys_begin <= phys_end);
+
+	let begin = util::down_align(phys_begin, memory::PAGE_SIZE) as usize;
+	let end = util::align(phys_end, memory::PAGE_SIZE) as usize;
+
+	let mut ptr = begin;
+	while ptr < end {
+		reserve(ptr as *const Void, 0);
+		ptr += memory::PAGE_SIZE;
+	}
+}
+
+/*
+ * A consistent snapshot of a zone's usage, produced by `Zone::get_stats`.
+ */
+#[derive(Clone, Copy)]
+pub struct ZoneStats {
+	/* The type of the zone. */
+	pub type_: Flags,
+	/* The total number of pages in the zone. */
+	pub pages_count: FrameID,
+	/* The number of allocated pages in the zone. */
+	pub allocated_pages: usize,
+	/* The order of the largest contiguous free frame, or `None` if the zone is full. */
+	pub largest_free_order: Option<FrameOrder>,
+}
+
+/*
+ * Returns a stats snapshot of every zone, in the same order as `ZONES`.
+ */
+pub fn zones_stats() -> [ZoneStats; 3] {
+	let zones = unsafe { ZONES.assume_init_mut() };
+	let mut stats = [ZoneStats {
+		type_: 0,
+		pages_count: 0,
+		allocated_pages: 0,
+		largest_free_order: None,
+	}; 3];
+
+	for i in 0..zones.len() {
+		let guard = MutexGuard::new(&mut zones[i]);
+		stats[i] = guard.get().get_stats();
+	}
+	stats
+}
+
 /*
  * Returns the total number of pages allocated by the buddy allocator.
  */
@@ -362,6 +479,27 @@ impl Zone {
 		(self.pages_count as usize) * memory::PAGE_SIZE
 	}
 
+	/*
+	 * Returns the order of the largest contiguous free frame currently available in the zone, by
+	 * scanning `free_list` from the highest order down. Returns `None` if the zone has no free
+	 * frame at all.
+	 */
+	pub fn get_largest_free_order(&self) -> Option<FrameOrder> {
+		self.free_list.iter().enumerate().rev().find(|(_, f)| f.is_some()).map(|(o, _)| o as _)
+	}
+
+	/*
+	 * Returns a consistent snapshot of the zone's usage.
+	 */
+	pub fn get_stats(&self) -> ZoneStats {
+		ZoneStats {
+			type_: self.type_,
+			pages_count: self.get_pages_count(),
+			allocated_pages: self.get_allocated_pages(),
+			largest_free_order: self.get_largest_free_order(),
+		}
+	}
+
 	/*
 	 * Returns an available frame owned by this zone, with an order of at least `order`.
 	 */
@@ -375,6 +513,34 @@ impl Zone {
 		None
 	}
 
+	/*
+	 * Searches the free lists for the free block that currently owns `frame_id`, which might be
+	 * the head of a block coalesced from several frames. Returns `None` if `frame_id` is used or
+	 * out of range.
+	 */
+	pub fn find_free_block(&self, frame_id: FrameID) -> Option<&'static mut Frame> {
+		for order in (0..=MAX_ORDER).rev() {
+			if let Some(first) = self.free_list[order as usize] {
+				let mut frame = first;
+
+				loop {
+					let f = unsafe { &mut *frame };
+					let id = f.get_id(self);
+					let size = util::pow2(order as _) as FrameID;
+					if frame_id >= id && frame_id < id + size {
+						return Some(f);
+					}
+
+					if f.next == id {
+						break;
+					}
+					frame = self.get_frame(f.next);
+				}
+			}
+		}
+		None
+	}
+
 	/*
 	 * Returns the identifier for the frame at the given pointer `ptr`. The pointer must point to
 	 * the frame itself, not the Frame structure.
@@ -570,6 +736,46 @@ impl Frame {
 		}
 	}
 
+	/*
+	 * Like `split`, but instead of always keeping `self`'s own frame, keeps splitting whichever
+	 * half covers `target` until it reaches order `order`. The returned frame is the one covering
+	 * `target`, unlinked from the free list; every other half produced along the way is linked
+	 * back into `zone`'s free list.
+	 *
+	 * The frame must not be marked as used and must cover `target`.
+	 */
+	pub fn split_at(&mut self, zone: &mut Zone, target: FrameID, order: FrameOrder)
+		-> &'static mut Frame {
+		debug_assert!(!self.is_used());
+		debug_assert!(self.order >= order);
+
+		self.unlink(zone);
+
+		let mut id = self.get_id(zone);
+		let mut cur_order = self.order;
+		while cur_order > order {
+			cur_order -= 1;
+
+			let half = util::pow2(cur_order as _) as FrameID;
+			let (kept, dropped) = if target < id + half {
+				(id, id + half)
+			} else {
+				(id + half, id)
+			};
+
+			let dropped_frame = unsafe { &mut *zone.get_frame(dropped) };
+			debug_assert!(!dropped_frame.is_used());
+			dropped_frame.order = cur_order;
+			dropped_frame.link(zone);
+
+			id = kept;
+		}
+
+		let kept_frame = unsafe { &mut *zone.get_frame(id) };
+		kept_frame.order = cur_order;
+		kept_frame
+	}
+
 	/*
 	 * Coealesces the frame in zone `zone` with free buddy blocks recursively until no buddy is
 	 * available anymore. Buddies that are merges with the frame are unlinked. The order of the
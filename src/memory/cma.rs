@@ -0,0 +1,168 @@
+/*
+ * This module implements a Contiguous Memory Allocator (CMA).
+ *
+ * Unlike the buddy allocator, which can only serve power-of-two-sized, naturally aligned frames,
+ * the CMA hands out physically contiguous, page-aligned buffers of an arbitrary page count and
+ * alignment, which some device drivers require. It manages its reserved region with a simple
+ * page-granular bitmap rather than with the buddy free lists, so the region it covers is never
+ * handed out by `buddy::alloc`.
+ */
+
+use crate::memory;
+use crate::memory::Void;
+use crate::util;
+use crate::util::lock::Mutex;
+use crate::util::lock::MutexGuard;
+
+/*
+ * The CMA's reserved region and the bitmap tracking its pages.
+ */
+struct Cma {
+	/* The physical address of the first page of the region. */
+	begin: *mut Void,
+	/* The number of pages in the region. */
+	pages_count: usize,
+
+	/* A pointer to the bitmap tracking which pages are in use, one bit per page. The bitmap
+	 * itself lives at the beginning of the region, ahead of `begin`. */
+	bitmap: *mut u8,
+}
+
+// Safe because every access to the region is guarded by `CMA`'s mutex.
+unsafe impl Send for Cma {}
+
+/*
+ * The CMA's state. `None` until `init` has reserved a usable region.
+ */
+static mut CMA: Mutex<Option<Cma>> = Mutex::new(None);
+
+/*
+ * Tells whether bit `i` of `bitmap` is set.
+ */
+unsafe fn bit_is_set(bitmap: *mut u8, i: usize) -> bool {
+	(*bitmap.add(i / 8) >> (i % 8)) & 1 == 1
+}
+
+/*
+ * Sets bit `i` of `bitmap`.
+ */
+unsafe fn bit_set(bitmap: *mut u8, i: usize) {
+	*bitmap.add(i / 8) |= 1 << (i % 8);
+}
+
+/*
+ * Clears bit `i` of `bitmap`.
+ */
+unsafe fn bit_clear(bitmap: *mut u8, i: usize) {
+	*bitmap.add(i / 8) &= !(1 << (i % 8));
+}
+
+/*
+ * Reserves the physical region of `region_size` bytes beginning at `region_begin` for the CMA.
+ *
+ * The beginning of the region is used to store the page bitmap; the remainder, once page-aligned,
+ * is the actual pool handed out by `cma_alloc`.
+ */
+pub fn init(region_begin: *mut Void, region_size: usize) {
+	let virt_begin = memory::kern_to_virt(region_begin) as *mut u8;
+
+	// Reserve one bit per page for the whole region; the pages actually usable once the bitmap
+	// itself has been carved out are fewer, but that only makes the bitmap slightly oversized.
+	let max_pages = region_size / memory::PAGE_SIZE;
+	let bitmap_size = (max_pages + 7) / 8;
+
+	let pool_begin = util::align(
+		(region_begin as usize) + bitmap_size,
+		memory::PAGE_SIZE,
+	) as *mut Void;
+	if (pool_begin as usize) >= (region_begin as usize) + region_size {
+		return;
+	}
+
+	let pages_count =
+		((region_begin as usize) + region_size - (pool_begin as usize)) / memory::PAGE_SIZE;
+
+	unsafe {
+		util::memset(virt_begin as _, 0, bitmap_size);
+	}
+
+	let mut guard = unsafe {
+		MutexGuard::new(&mut CMA)
+	};
+	*guard.get_mut() = Some(Cma {
+		begin: pool_begin,
+		pages_count,
+
+		bitmap: virt_begin,
+	});
+}
+
+/*
+ * Scans the CMA's bitmap for `count` consecutive free pages whose start satisfies `align` (in
+ * bytes, must be a power of two), marks them used and returns the physical address of the first
+ * page. Returns `Err(())` if no such run exists, or if the CMA hasn't been initialized.
+ */
+pub fn cma_alloc(count: usize, align: usize) -> Result<*mut Void, ()> {
+	debug_assert!(align.is_power_of_two());
+
+	let mut guard = unsafe {
+		MutexGuard::new(&mut CMA)
+	};
+	let cma = guard.get_mut().as_mut().ok_or(())?;
+
+	if count == 0 || count > cma.pages_count {
+		return Err(());
+	}
+
+	let mut start = 0;
+	while start + count <= cma.pages_count {
+		// `start` is an index relative to `cma.begin`, which is only page-aligned, so checking
+		// `start % align_pages` would accept a run whose absolute physical address isn't actually
+		// `align`-aligned. Check the real address instead.
+		let addr = cma.begin as usize + start * memory::PAGE_SIZE;
+		if addr % align != 0 {
+			start += 1;
+			continue;
+		}
+
+		let run_end = (start..start + count)
+			.find(|&i| unsafe { bit_is_set(cma.bitmap, i) });
+		match run_end {
+			Some(i) => start = i + 1,
+			None => {
+				for i in start..start + count {
+					unsafe {
+						bit_set(cma.bitmap, i);
+					}
+				}
+
+				return Ok((cma.begin as usize + start * memory::PAGE_SIZE) as _);
+			}
+		}
+	}
+
+	Err(())
+}
+
+/*
+ * Frees `count` pages previously returned by `cma_alloc` starting at physical address `ptr`.
+ */
+pub fn cma_free(ptr: *const Void, count: usize) {
+	let mut guard = unsafe {
+		MutexGuard::new(&mut CMA)
+	};
+	let cma = match guard.get_mut().as_mut() {
+		Some(c) => c,
+		None => return,
+	};
+
+	debug_assert!(util::is_aligned(ptr, memory::PAGE_SIZE));
+	let start = ((ptr as usize) - (cma.begin as usize)) / memory::PAGE_SIZE;
+	debug_assert!(start + count <= cma.pages_count);
+
+	for i in start..start + count {
+		unsafe {
+			bit_clear(cma.bitmap, i);
+		}
+	}
+}
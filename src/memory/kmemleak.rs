@@ -0,0 +1,201 @@
+/*
+ * This module implements an opt-in, `kmemleak`-style detector over the tagged kernel allocations
+ * of `crate::memory::stats`: every `kmalloc_tagged` call registers its allocation here and every
+ * `kfree_tagged` call removes it, so that a `scan` pass can flag ranges that no longer seem to be
+ * reachable from anywhere the kernel still runs.
+ *
+ * The registry is kept sorted by base address so `scan` can binary-search it while walking
+ * memory word by word. `scan` starts from the roots registered with `register_root` (the
+ * kernel's static data/BSS section and the live stacks) as an initial gray set, then repeatedly
+ * scans each gray block, adding to the worklist every tracked block that a word of it points
+ * into, until the worklist drains. Anything still unmarked after two consecutive passes is
+ * reported as a suspected leak; the two-pass rule tolerates a pointer that is transiently held
+ * only in a register rather than spilled to memory, which would otherwise look like a leak on a
+ * single pass.
+ */
+
+use crate::memory::Void;
+use crate::memory::stats::MallocType;
+use crate::util::container::vec::Vec;
+use crate::util::lock::Mutex;
+use crate::util::lock::MutexGuard;
+
+/*
+ * A single tracked allocation.
+ */
+struct Block {
+	/* The base address of the allocation. */
+	base: usize,
+	/* The size in bytes of the allocation. */
+	size: usize,
+	/* The name of the `MallocType` the allocation was tagged with. */
+	type_name: &'static str,
+
+	/* The number of consecutive `scan` passes that failed to reach this block. */
+	unseen_scans: u32,
+}
+
+/*
+ * A root range to scan from: the kernel's static data/BSS section, or a live stack.
+ */
+#[derive(Clone, Copy)]
+struct Root {
+	/* The beginning of the range. */
+	begin: usize,
+	/* The end of the range (exclusive). */
+	end: usize,
+}
+
+/*
+ * The address-sorted registry of every tracked, currently live allocation.
+ */
+static REGISTRY: Mutex<Vec<Block>> = Mutex::new(Vec::new());
+/*
+ * The set of memory ranges scanned as the initial gray set of a `scan` pass.
+ */
+static ROOTS: Mutex<Vec<Root>> = Mutex::new(Vec::new());
+
+/*
+ * Registers `[begin, end)` as a root to scan from, in addition to the tracked allocations
+ * themselves. Meant to be called once for the kernel's static data/BSS section, and once per
+ * live thread for its stack.
+ */
+pub fn register_root(begin: *const Void, end: *const Void) {
+	let mut guard = MutexGuard::new(&ROOTS);
+
+	let _ = guard.get_mut().push(Root {
+		begin: begin as usize,
+		end: end as usize,
+	});
+}
+
+/*
+ * Registers an allocation of `size` bytes at `ptr`, tagged with `ty`, for leak tracking. Called
+ * by `stats::kmalloc_tagged`.
+ */
+pub fn track(ptr: *const Void, size: usize, ty: &MallocType) {
+	let mut guard = MutexGuard::new(&REGISTRY);
+	let registry = guard.get_mut();
+
+	let base = ptr as usize;
+	let index = registry.iter().position(|b| b.base > base).unwrap_or(registry.len());
+	let _ = registry.insert(index, Block {
+		base,
+		size,
+		type_name: ty.get_name(),
+		unseen_scans: 0,
+	});
+}
+
+/*
+ * Removes the tracking of the allocation at `ptr`. Called by `stats::kfree_tagged`.
+ */
+pub fn untrack(ptr: *const Void) {
+	let mut guard = MutexGuard::new(&REGISTRY);
+	let registry = guard.get_mut();
+
+	let base = ptr as usize;
+	if let Some(index) = registry.iter().position(|b| b.base == base) {
+		registry.remove(index);
+	}
+}
+
+/*
+ * Returns the index of the tracked block whose range `[base, base + size)` contains `addr`, if
+ * any, by binary-searching the address-sorted registry.
+ */
+fn find_containing(registry: &Vec<Block>, addr: usize) -> Option<usize> {
+	let mut lo = 0;
+	let mut hi = registry.len();
+
+	while lo < hi {
+		let mid = lo + (hi - lo) / 2;
+		if registry[mid].base <= addr {
+			lo = mid + 1;
+		} else {
+			hi = mid;
+		}
+	}
+
+	if lo == 0 {
+		return None;
+	}
+
+	let candidate = &registry[lo - 1];
+	if addr < candidate.base + candidate.size {
+		Some(lo - 1)
+	} else {
+		None
+	}
+}
+
+/*
+ * Scans every word of the word-aligned range `[begin, end)`, and for each word whose value falls
+ * inside a tracked block not yet in `reached`, marks it reached and pushes it onto `worklist`.
+ */
+fn scan_range(registry: &Vec<Block>, begin: usize, end: usize, reached: &mut [bool],
+	worklist: &mut Vec<usize>) {
+	let word_size = core::mem::size_of::<usize>();
+	let mut word = (begin + word_size - 1) / word_size * word_size;
+
+	while word + word_size <= end {
+		let value = unsafe { *(word as *const usize) };
+
+		if let Some(index) = find_containing(registry, value) {
+			if !reached[index] {
+				reached[index] = true;
+				let _ = worklist.push(index);
+			}
+		}
+
+		word += word_size;
+	}
+}
+
+/*
+ * Performs a `scan` pass over the tracked allocations and returns the number of allocations
+ * flagged as a suspected leak during this pass.
+ *
+ * A block is reachable if a pointer into it shows up while walking the registered roots, or
+ * while walking any block already found reachable (the gray set), until the worklist drains.
+ * Anything still unreached after two consecutive calls to `scan` is logged and counted as a
+ * suspected leak.
+ */
+pub fn scan() -> usize {
+	let roots_guard = MutexGuard::new(&ROOTS);
+	let roots = roots_guard.get();
+
+	let mut registry_guard = MutexGuard::new(&REGISTRY);
+	let registry = registry_guard.get_mut();
+
+	let mut reached = Vec::new();
+	for _ in 0..registry.len() {
+		let _ = reached.push(false);
+	}
+	let mut worklist = Vec::new();
+
+	for root in roots.iter() {
+		scan_range(registry, root.begin, root.end, &mut reached, &mut worklist);
+	}
+
+	while let Some(index) = worklist.pop() {
+		let (base, size) = (registry[index].base, registry[index].size);
+		scan_range(registry, base, base + size, &mut reached, &mut worklist);
+	}
+
+	let mut leaks = 0;
+	for (i, block) in registry.iter_mut().enumerate() {
+		if reached[i] {
+			block.unseen_scans = 0;
+			continue;
+		}
+
+		block.unseen_scans += 1;
+		if block.unseen_scans >= 2 {
+			println!("kmemleak: suspected leak of {} bytes at {:#x} (type: {})",
+				block.size, block.base, block.type_name);
+			leaks += 1;
+		}
+	}
+	leaks
+}
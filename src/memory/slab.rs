@@ -0,0 +1,365 @@
+/*
+ * This module implements the slab allocator, which provides fixed-size object caches backed by
+ * the buddy allocator, so kernel subsystems stop wasting whole pages on small structures.
+ *
+ * A cache is made of slabs. Each slab is a single frame allocated through the buddy allocator and
+ * carved into objects of the cache's size. The free objects of a slab are linked together: the
+ * first word of a free object holds the index of the next free object, so no extra memory is
+ * required to keep track of them. A cache keeps its slabs on three intrusive lists (full, partial
+ * and empty) so that `alloc` can always find a candidate slab in constant time.
+ */
+
+use core::mem::size_of;
+use core::ptr::NonNull;
+use crate::memory;
+use crate::memory::Void;
+use crate::memory::buddy;
+use crate::memory::buddy::FrameOrder;
+use crate::util;
+use crate::util::lock::Mutex;
+use crate::util::lock::MutexGuard;
+
+/*
+ * Value used by `Slab::next_avail` to indicate that the slab has no free object left.
+ */
+const NO_AVAIL: u32 = !(0 as u32);
+
+/*
+ * The header placed at the beginning of a slab's frame. It is immediately followed by the
+ * objects themselves.
+ */
+#[repr(C)]
+struct Slab {
+	/* Link to the previous slab of the cache's current list. */
+	prev: *mut Slab,
+	/* Link to the next slab of the cache's current list. */
+	next: *mut Slab,
+
+	/* The index of the next available object, or `NO_AVAIL` if the slab is full. */
+	next_avail: u32,
+	/* The number of available objects left in the slab. */
+	available: u32,
+}
+
+impl Slab {
+	/*
+	 * Returns a pointer to the beginning of the slab's objects array.
+	 * `objects_offset` is the offset of the array from the beginning of the slab, computed by the
+	 * owning cache.
+	 */
+	fn get_objects(&mut self, objects_offset: usize) -> *mut Void {
+		((self as *mut Self as usize) + objects_offset) as _
+	}
+
+	/*
+	 * Returns a pointer to the object at index `i`, of size `obj_size`, in the slab whose objects
+	 * begin at `objects`.
+	 */
+	fn get_object(objects: *mut Void, obj_size: usize, i: u32) -> *mut Void {
+		((objects as usize) + (i as usize) * obj_size) as _
+	}
+
+	/*
+	 * Initializes the free list of a freshly allocated slab made of `objects_count` objects of
+	 * size `obj_size`.
+	 */
+	fn init_free_list(&mut self, objects_offset: usize, obj_size: usize, objects_count: u32) {
+		let objects = self.get_objects(objects_offset);
+
+		for i in 0..objects_count {
+			let obj = Self::get_object(objects, obj_size, i);
+			let next = if i + 1 < objects_count {
+				i + 1
+			} else {
+				NO_AVAIL
+			};
+
+			unsafe {
+				*(obj as *mut u32) = next;
+			}
+		}
+
+		self.next_avail = 0;
+		self.available = objects_count;
+	}
+
+	/*
+	 * Takes an object from the slab's free list and returns a pointer to it.
+	 * The slab must not be full.
+	 */
+	fn take(&mut self, objects_offset: usize, obj_size: usize) -> *mut Void {
+		debug_assert!(self.next_avail != NO_AVAIL);
+
+		let objects = self.get_objects(objects_offset);
+		let obj = Self::get_object(objects, obj_size, self.next_avail);
+
+		self.next_avail = unsafe { *(obj as *mut u32) };
+		self.available -= 1;
+
+		obj
+	}
+
+	/*
+	 * Gives back object `obj` to the slab's free list.
+	 * `objects_offset` and `obj_size` come from the owning cache.
+	 */
+	fn give_back(&mut self, objects_offset: usize, obj_size: usize, obj: *mut Void) {
+		let objects = self.get_objects(objects_offset);
+		let index = ((obj as usize) - (objects as usize)) / obj_size;
+
+		unsafe {
+			*(obj as *mut u32) = self.next_avail;
+		}
+		self.next_avail = index as u32;
+		self.available += 1;
+	}
+}
+
+/*
+ * Removes `slab` from the list whose head is `list`.
+ */
+unsafe fn list_remove(list: &mut Option<NonNull<Slab>>, slab: *mut Slab) {
+	let s = &mut *slab;
+
+	if !s.prev.is_null() {
+		(*s.prev).next = s.next;
+	} else if *list == NonNull::new(slab) {
+		*list = NonNull::new(s.next);
+	}
+
+	if !s.next.is_null() {
+		(*s.next).prev = s.prev;
+	}
+
+	s.prev = core::ptr::null_mut();
+	s.next = core::ptr::null_mut();
+}
+
+/*
+ * Inserts `slab` at the front of the list whose head is `list`.
+ */
+unsafe fn list_push_front(list: &mut Option<NonNull<Slab>>, slab: *mut Slab) {
+	let s = &mut *slab;
+
+	s.prev = core::ptr::null_mut();
+	s.next = list.map(|n| n.as_ptr()).unwrap_or(core::ptr::null_mut());
+	if let Some(head) = *list {
+		(*head.as_ptr()).prev = slab;
+	}
+
+	*list = NonNull::new(slab);
+}
+
+/*
+ * A cache of fixed-size objects, backed by the buddy allocator.
+ */
+pub struct SlabCache {
+	/* The size in bytes of an object served by this cache. */
+	obj_size: usize,
+	/* The alignment required by objects served by this cache. */
+	align: usize,
+	/* The order of the frames backing the cache's slabs. */
+	order: FrameOrder,
+	/* The offset of the objects array from the beginning of a slab's frame. */
+	objects_offset: usize,
+	/* The number of objects held by a single slab. */
+	objects_per_slab: u32,
+
+	/* The list of slabs with no free object left. */
+	full: Option<NonNull<Slab>>,
+	/* The list of slabs with at least one free and one used object. */
+	partial: Option<NonNull<Slab>>,
+	/* The list of slabs with no used object. */
+	empty: Option<NonNull<Slab>>,
+}
+
+// Safe because every access to the intrusive lists is guarded by a `Mutex` at the call site.
+unsafe impl Send for SlabCache {}
+
+impl SlabCache {
+	/*
+	 * Creates a new cache serving objects of size `obj_size`, aligned on `align` bytes.
+	 * `align` must be a power of two.
+	 */
+	pub fn new(obj_size: usize, align: usize) -> Self {
+		debug_assert!(obj_size > 0);
+		debug_assert!(align.is_power_of_two());
+
+		let objects_offset = util::align(size_of::<Slab>(), align);
+
+		let mut order: FrameOrder = 0;
+		while buddy::get_frame_size(order) < objects_offset + obj_size {
+			order += 1;
+		}
+
+		let objects_per_slab = ((buddy::get_frame_size(order) - objects_offset) / obj_size) as u32;
+		debug_assert!(objects_per_slab > 0);
+
+		Self {
+			obj_size,
+			align,
+			order,
+			objects_offset,
+			objects_per_slab,
+
+			full: None,
+			partial: None,
+			empty: None,
+		}
+	}
+
+	/*
+	 * Allocates a new slab for the cache and pushes it onto the `empty` list.
+	 */
+	fn alloc_slab(&mut self) -> Result<(), ()> {
+		let ptr = buddy::alloc_kernel(self.order)?;
+		let slab = ptr as *mut Slab;
+
+		unsafe {
+			(*slab).prev = core::ptr::null_mut();
+			(*slab).next = core::ptr::null_mut();
+			(*slab).init_free_list(self.objects_offset, self.obj_size, self.objects_per_slab);
+
+			list_push_front(&mut self.empty, slab);
+		}
+
+		Ok(())
+	}
+
+	/*
+	 * Allocates an object from the cache.
+	 */
+	pub fn alloc(&mut self) -> Result<*mut Void, ()> {
+		if self.partial.is_none() {
+			if let Some(head) = self.empty {
+				unsafe {
+					list_remove(&mut self.empty, head.as_ptr());
+					list_push_front(&mut self.partial, head.as_ptr());
+				}
+			} else {
+				self.alloc_slab()?;
+
+				let head = self.empty.unwrap();
+				unsafe {
+					list_remove(&mut self.empty, head.as_ptr());
+					list_push_front(&mut self.partial, head.as_ptr());
+				}
+			}
+		}
+
+		let slab = self.partial.unwrap().as_ptr();
+		let obj = unsafe { (*slab).take(self.objects_offset, self.obj_size) };
+
+		if unsafe { (*slab).next_avail } == NO_AVAIL {
+			unsafe {
+				list_remove(&mut self.partial, slab);
+				list_push_front(&mut self.full, slab);
+			}
+		}
+
+		Ok(obj)
+	}
+
+	/*
+	 * Frees object `obj`, which must have been allocated by this same cache.
+	 */
+	pub fn free(&mut self, obj: *mut Void) {
+		let frame_size = buddy::get_frame_size(self.order);
+		let slab = util::down_align(obj, frame_size) as *mut Slab;
+
+		let was_full = unsafe { (*slab).next_avail } == NO_AVAIL;
+
+		unsafe {
+			(*slab).give_back(self.objects_offset, self.obj_size, obj);
+		}
+
+		if was_full {
+			unsafe {
+				list_remove(&mut self.full, slab);
+				list_push_front(&mut self.partial, slab);
+			}
+		}
+
+		if unsafe { (*slab).available } == self.objects_per_slab {
+			unsafe {
+				list_remove(&mut self.partial, slab);
+			}
+			buddy::free_kernel(slab as _, self.order);
+		}
+	}
+}
+
+/*
+ * The kmalloc caches' sizes, from the smallest to the largest. Every size is a power of two.
+ */
+const KMALLOC_SIZES: [usize; 8] = [8, 16, 32, 64, 128, 256, 512, 1024];
+
+/*
+ * The caches backing `kmalloc`/`kfree`, one per entry of `KMALLOC_SIZES`.
+ */
+static mut KMALLOC_CACHES: Option<[Mutex<SlabCache>; KMALLOC_SIZES.len()]> = None;
+
+/*
+ * Initializes the `kmalloc` caches. Must be called once, after the buddy allocator has been
+ * initialized.
+ */
+pub fn init() {
+	unsafe {
+		KMALLOC_CACHES = Some([
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[0], KMALLOC_SIZES[0])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[1], KMALLOC_SIZES[1])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[2], KMALLOC_SIZES[2])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[3], KMALLOC_SIZES[3])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[4], KMALLOC_SIZES[4])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[5], KMALLOC_SIZES[5])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[6], KMALLOC_SIZES[6])),
+			Mutex::new(SlabCache::new(KMALLOC_SIZES[7], KMALLOC_SIZES[7])),
+		]);
+	}
+}
+
+/*
+ * Returns the index of the smallest kmalloc cache able to serve `size` bytes, or `None` if no
+ * cache is large enough (the request must then fall back to a direct buddy allocation).
+ */
+fn kmalloc_cache_index(size: usize) -> Option<usize> {
+	KMALLOC_SIZES.iter().position(|&s| s >= size)
+}
+
+/*
+ * Allocates `size` bytes of kernel memory.
+ *
+ * Requests fitting in the largest kmalloc cache are served by the matching slab cache. Larger
+ * requests fall back to a direct buddy allocation.
+ */
+pub fn kmalloc(size: usize) -> Result<*mut Void, ()> {
+	if size > *KMALLOC_SIZES.last().unwrap() {
+		let pages = (size + memory::PAGE_SIZE - 1) / memory::PAGE_SIZE;
+		let order = buddy::get_order(pages);
+		return buddy::alloc_kernel(order);
+	}
+
+	let index = kmalloc_cache_index(size).ok_or(())?;
+	let caches = unsafe { KMALLOC_CACHES.as_mut() }.ok_or(())?;
+	let mut guard = MutexGuard::new(&mut caches[index]);
+	guard.get_mut().alloc()
+}
+
+/*
+ * Frees memory previously allocated with `kmalloc`. `size` must be the same size as given to the
+ * `kmalloc` call that returned `ptr`.
+ */
+pub fn kfree(ptr: *mut Void, size: usize) {
+	if size > *KMALLOC_SIZES.last().unwrap() {
+		let pages = (size + memory::PAGE_SIZE - 1) / memory::PAGE_SIZE;
+		let order = buddy::get_order(pages);
+		buddy::free_kernel(ptr, order);
+		return;
+	}
+
+	if let Some(index) = kmalloc_cache_index(size) {
+		let caches = unsafe { KMALLOC_CACHES.as_mut() }.unwrap();
+		let mut guard = MutexGuard::new(&mut caches[index]);
+		guard.get_mut().free(ptr);
+	}
+}
@@ -0,0 +1,184 @@
+/*
+ * This module keeps track of where kernel memory goes, mirroring how UMA/malloc expose per-type
+ * and per-zone usage.
+ *
+ * Each subsystem that performs tagged allocations owns a static `MallocType` describing what it
+ * allocates; every registered type is tracked in a global registry so that `dump_stats` can walk
+ * it alongside the buddy allocator's zones to produce a full snapshot of memory usage.
+ */
+
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use crate::memory::Void;
+use crate::memory::buddy;
+use crate::memory::kmemleak;
+use crate::memory::slab;
+use crate::util::lock::Mutex;
+use crate::util::lock::MutexGuard;
+
+/*
+ * The maximum number of `MallocType`s that can be registered.
+ */
+const MAX_TYPES: usize = 32;
+
+/*
+ * Descriptor of a kind of allocation, tracking its current and cumulative usage.
+ */
+pub struct MallocType {
+	/* The name of the type, for reporting purposes. */
+	name: &'static str,
+
+	/* The number of bytes currently allocated for this type. */
+	current_bytes: AtomicUsize,
+	/* The number of allocations currently alive for this type. */
+	current_count: AtomicUsize,
+	/* The total number of allocations performed for this type since boot. */
+	total_allocations: AtomicUsize,
+	/* The highest value `current_bytes` has ever reached. */
+	peak_bytes: AtomicUsize,
+}
+
+impl MallocType {
+	/*
+	 * Creates a new, empty descriptor named `name`.
+	 */
+	pub const fn new(name: &'static str) -> Self {
+		Self {
+			name,
+
+			current_bytes: AtomicUsize::new(0),
+			current_count: AtomicUsize::new(0),
+			total_allocations: AtomicUsize::new(0),
+			peak_bytes: AtomicUsize::new(0),
+		}
+	}
+
+	/*
+	 * Records an allocation of `size` bytes for this type.
+	 */
+	fn record_alloc(&self, size: usize) {
+		let bytes = self.current_bytes.fetch_add(size, Ordering::Relaxed) + size;
+		self.current_count.fetch_add(1, Ordering::Relaxed);
+		self.total_allocations.fetch_add(1, Ordering::Relaxed);
+		self.peak_bytes.fetch_max(bytes, Ordering::Relaxed);
+	}
+
+	/*
+	 * Records the freeing of `size` bytes previously allocated for this type.
+	 */
+	fn record_free(&self, size: usize) {
+		self.current_bytes.fetch_sub(size, Ordering::Relaxed);
+		self.current_count.fetch_sub(1, Ordering::Relaxed);
+	}
+
+	/*
+	 * Returns the name of the type, as given to `new`.
+	 */
+	pub fn get_name(&self) -> &'static str {
+		self.name
+	}
+
+	/*
+	 * Returns a consistent snapshot of the type's counters.
+	 */
+	pub fn get_stats(&self) -> MallocTypeStats {
+		MallocTypeStats {
+			name: self.name,
+			current_bytes: self.current_bytes.load(Ordering::Relaxed),
+			current_count: self.current_count.load(Ordering::Relaxed),
+			total_allocations: self.total_allocations.load(Ordering::Relaxed),
+			peak_bytes: self.peak_bytes.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/*
+ * A consistent snapshot of a `MallocType`'s counters, produced by `MallocType::get_stats`.
+ */
+#[derive(Clone, Copy)]
+pub struct MallocTypeStats {
+	/* The name of the type. */
+	pub name: &'static str,
+	/* The number of bytes currently allocated for this type. */
+	pub current_bytes: usize,
+	/* The number of allocations currently alive for this type. */
+	pub current_count: usize,
+	/* The total number of allocations performed for this type since boot. */
+	pub total_allocations: usize,
+	/* The highest value `current_bytes` has ever reached. */
+	pub peak_bytes: usize,
+}
+
+/*
+ * The global registry of every `MallocType` registered with `register_type`.
+ */
+static mut TYPES: Mutex<[Option<&'static MallocType>; MAX_TYPES]> = Mutex::new([None; MAX_TYPES]);
+
+/*
+ * Registers `ty` into the global type registry, so that it is included in `dump_stats`.
+ *
+ * The function panics if the registry is already full; `MAX_TYPES` is expected to comfortably
+ * cover every subsystem tagging its allocations.
+ */
+pub fn register_type(ty: &'static MallocType) {
+	let mut guard = unsafe {
+		MutexGuard::new(&mut TYPES)
+	};
+	let types = guard.get_mut();
+
+	let slot = types.iter_mut().find(|t| t.is_none())
+		.expect("MallocType registry is full");
+	*slot = Some(ty);
+}
+
+/*
+ * Allocates `size` bytes through `kmalloc`, tagging the allocation under `ty` for accounting and
+ * leak tracking.
+ */
+pub fn kmalloc_tagged(size: usize, ty: &MallocType) -> Result<*mut Void, ()> {
+	let ptr = slab::kmalloc(size)?;
+	ty.record_alloc(size);
+	kmemleak::track(ptr, size, ty);
+	Ok(ptr)
+}
+
+/*
+ * Frees memory previously allocated with `kmalloc_tagged` under the same `ty` and `size`.
+ */
+pub fn kfree_tagged(ptr: *mut Void, size: usize, ty: &MallocType) {
+	kmemleak::untrack(ptr);
+	slab::kfree(ptr, size);
+	ty.record_free(size);
+}
+
+/*
+ * A full snapshot of the kernel's memory usage, produced by `dump_stats`.
+ */
+pub struct MemStats {
+	/* The stats of every buddy allocator zone. */
+	pub zones: [buddy::ZoneStats; 3],
+	/* The stats of every registered `MallocType`, in registration order. */
+	pub types: [Option<MallocTypeStats>; MAX_TYPES],
+}
+
+/*
+ * Walks `ZONES` and the type registry to produce a consistent snapshot of memory usage, letting
+ * the kernel detect which subsystem is leaking or fragmenting memory.
+ */
+pub fn dump_stats() -> MemStats {
+	let mut types = [None; MAX_TYPES];
+
+	{
+		let guard = unsafe {
+			MutexGuard::new(&mut TYPES)
+		};
+		for (i, ty) in guard.get().iter().enumerate() {
+			types[i] = ty.map(|t| t.get_stats());
+		}
+	}
+
+	MemStats {
+		zones: buddy::zones_stats(),
+		types,
+	}
+}
@@ -13,9 +13,11 @@ use core::ptr::NonNull;
 use core::ptr;
 use crate::errno::Errno;
 use crate::util::boxed::Box;
+use crate::util::container::ring_buffer::RingBuffer;
 use crate::util::container::string::String;
 use crate::util::container::vec::Vec;
 use crate::util::lock::Mutex;
+use crate::util::ptr::arc::Arc;
 use crate::util::ptr::SharedPtr;
 
 /// Type representing a Media Access Control (MAC) address.
@@ -81,6 +83,10 @@ pub trait Interface {
 	/// Returns the list of addresses bound to the interface.
 	fn get_addresses(&self) -> &[BindAddress];
 
+	/// Returns a mutable reference to the list of addresses bound to the interface, for
+	/// `RTM_NEWADDR`/`RTM_DELADDR` to add or remove entries.
+	fn get_addresses_mut(&mut self) -> &mut Vec<BindAddress>;
+
 	/// Reads data from the network interface and writes it into `buff`.
 	///
 	/// The function returns the number of bytes read.
@@ -107,6 +113,40 @@ pub struct Route {
 }
 
 impl Route {
+	/// Creates a new instance.
+	///
+	/// `dst` is the destination address/subnet, or `None` for the default route. `iface` is the
+	/// name of the network interface the route goes through. `gateway` is the gateway's address.
+	/// `metric` is the route's priority, lowest first.
+	pub fn new(dst: Option<BindAddress>, iface: String, gateway: Address, metric: u32) -> Self {
+		Self {
+			dst,
+			iface,
+			gateway,
+			metric,
+		}
+	}
+
+	/// Returns the destination address/subnet, or `None` if this is the default route.
+	pub fn get_dst(&self) -> Option<&BindAddress> {
+		self.dst.as_ref()
+	}
+
+	/// Returns the name of the network interface the route goes through.
+	pub fn get_iface(&self) -> &String {
+		&self.iface
+	}
+
+	/// Returns the gateway's address.
+	pub fn get_gateway(&self) -> &Address {
+		&self.gateway
+	}
+
+	/// Returns the route's metric.
+	pub fn get_metric(&self) -> u32 {
+		self.metric
+	}
+
 	/// Tells whether the route matches the given address.
 	pub fn is_matching(&self, addr: &Address) -> bool {
 		// Check gateway
@@ -175,6 +215,18 @@ pub fn unregister_iface(_name: &[u8]) {
 	todo!();
 }
 
+/// Runs `f` with mutable access to the interface named `name`, if one is registered.
+///
+/// This is the lookup `AF_PACKET`/`SOCK_RAW` sockets use to reach the `Interface` they are bound
+/// to for transmission: unlike [`get_iface`], it hands out a scoped `&mut dyn Interface` instead
+/// of a [`SharedPtr`], so it works with [`INTERFACES`]'s current `Vec<Box<dyn Interface>>`
+/// storage without needing that storage reshaped first.
+pub fn with_iface_mut<R>(name: &[u8], f: impl FnOnce(&mut dyn Interface) -> R) -> Option<R> {
+	let mut interfaces = INTERFACES.lock();
+	let iface = interfaces.iter_mut().find(|iface| iface.get_name() == name)?;
+	Some(f(iface.as_mut()))
+}
+
 /// Returns the network interface with the given name.
 ///
 /// If the interface doesn't exist, thhe function returns `None`.
@@ -194,6 +246,104 @@ pub fn get_iface_for(addr: Address) -> Option<SharedPtr<dyn Interface>> {
 	get_iface(&route.iface)
 }
 
+/// Pushes `frame`, prefixed with its 2-byte little-endian length, onto `ring`.
+///
+/// Fails with `EMSGSIZE` if the frame is larger than `u16::MAX`, or `ENOBUFS` if there isn't room
+/// for it right now.
+pub fn push_frame(ring: &mut RingBuffer<u8, Vec<u8>>, frame: &[u8]) -> Result<(), Errno> {
+	let len: u16 = frame.len().try_into().map_err(|_| errno!(EMSGSIZE))?;
+	if 2 + frame.len() > ring.get_available_len() {
+		return Err(errno!(ENOBUFS));
+	}
+
+	ring.write(&len.to_le_bytes());
+	ring.write(frame);
+
+	Ok(())
+}
+
+/// Pops the next length-prefixed frame off `ring` into `out`, truncating it to `out.len()` if it
+/// doesn't fit, and returns the number of bytes copied, or `0` if `ring` holds no full frame.
+pub fn pop_frame(ring: &mut RingBuffer<u8, Vec<u8>>, out: &mut [u8]) -> usize {
+	if ring.get_data_len() < 2 {
+		return 0;
+	}
+
+	let mut len_buf = [0u8; 2];
+	ring.read(&mut len_buf);
+	let len = u16::from_le_bytes(len_buf) as usize;
+
+	let n = len.min(out.len());
+	ring.read(&mut out[..n]);
+
+	// `out` may be shorter than the frame; drain the remainder straight from the ring through a
+	// small, fixed-size buffer instead of staging the whole (up to 64 KiB) frame on the stack.
+	let mut discard = [0u8; 256];
+	let mut remaining = len - n;
+	while remaining > 0 {
+		let chunk = remaining.min(discard.len());
+		ring.read(&mut discard[..chunk]);
+		remaining -= chunk;
+	}
+
+	n
+}
+
+/// An `AF_PACKET`/`SOCK_RAW` socket's capture state: the filter it is bound to and the frames
+/// matching it that are waiting to be read back out.
+///
+/// [`crate::file::buffer::socket::Socket`] creates one of these per raw socket and keeps an
+/// `Arc` to it alongside the copy it registers into [`PACKET_SOCKETS`], so an `ioctl` narrowing
+/// the filter and [`receive`] delivering a frame both see the same state.
+#[derive(Debug)]
+pub struct PacketBinding {
+	/// The interface name to capture from, or `None` to capture from every interface.
+	pub iface: Option<String>,
+	/// The EtherType to capture, or `None` to capture every frame regardless of it.
+	pub ethertype: Option<u16>,
+	/// The ring matching frames are copied into, for the owning socket to read back out.
+	pub rx: RingBuffer<u8, Vec<u8>>,
+}
+
+/// The `AF_PACKET`/`SOCK_RAW` sockets currently bound to capture raw frames.
+///
+/// Entries are never removed on socket close yet, the same gap [`unregister_iface`] already has
+/// for interfaces: nothing in this tree ties a `Buffer`'s teardown back to this list.
+pub static PACKET_SOCKETS: Mutex<Vec<Arc<Mutex<PacketBinding>>>> = Mutex::new(Vec::new());
+
+/// Registers `binding` so its socket starts receiving frames matching it.
+pub fn register_packet_socket(binding: Arc<Mutex<PacketBinding>>) -> Result<(), Errno> {
+	PACKET_SOCKETS.lock().push(binding)
+}
+
+/// Copies `raw`, as received from `iface_name`, into every registered [`PacketBinding`] it
+/// matches.
+fn deliver_to_packet_sockets(iface_name: &[u8], raw: &[u8]) {
+	if raw.len() < ETH_HEADER_LEN {
+		return;
+	}
+	let ethertype = u16::from_be_bytes([raw[12], raw[13]]);
+
+	let sockets = PACKET_SOCKETS.lock();
+	for binding in sockets.iter() {
+		let mut binding = binding.lock();
+		if let Some(name) = &binding.iface {
+			if name.as_bytes() != iface_name {
+				continue;
+			}
+		}
+		if let Some(want) = binding.ethertype {
+			if want != ethertype {
+				continue;
+			}
+		}
+
+		// Best-effort: a full ring drops the frame, same as a real packet socket's receive
+		// buffer overflowing.
+		let _ = push_frame(&mut binding.rx, raw);
+	}
+}
+
 /// A linked-list of buffers representing a packet being built.
 pub struct BuffList<'b> {
 	/// The buffer.
@@ -230,6 +380,21 @@ impl<'b> BuffList<'b> {
 		other
 	}
 
+	/// Returns a new list with the first `n` bytes removed from the front, for a layer to peel
+	/// its header off before handing the remainder up to the next layer in `receive`.
+	///
+	/// `n` must not exceed the length of the first buffer: a frame handed to the top of the
+	/// stack by [`receive`] starts as a single contiguous buffer, so no header a layer peels off
+	/// it ever straddles a buffer boundary.
+	pub fn skip_front(&self, n: usize) -> BuffList<'b> {
+		BuffList {
+			b: &self.b[n..],
+
+			next: self.next,
+			next_len: self.next_len,
+		}
+	}
+
 	/// Collects all buffers into one.
 	pub fn collect(&self) -> Result<Vec<u8>, Errno> {
 		let len = self.len();
@@ -258,7 +423,19 @@ impl<'b> BuffList<'b> {
 ///
 /// A layer stack acts as a pipeline, passing packets from one layer to the other.
 pub trait Layer {
-	// TODO receive
+	/// Receives data from the given buffer.
+	///
+	/// Arguments:
+	/// - `buff` is the list of buffers composing the packet being received, as handed up from the
+	///   layer below, with every lower layer's header already peeled off.
+	/// - `next` is the function called to pass the buffers list, with this layer's own header
+	///   peeled off the front, up to the next layer.
+	fn receive<'c, F>(
+		&self,
+		buff: BuffList<'c>,
+		next: F
+	) -> Result<(), Errno>
+		where Self: Sized, F: Fn(BuffList<'c>) -> Result<(), Errno>;
 
 	/// Transmits data in the given buffer.
 	///
@@ -272,3 +449,116 @@ pub trait Layer {
 	) -> Result<(), Errno>
 		where Self: Sized, F: Fn(BuffList<'c>) -> Result<(), Errno>;
 }
+
+/// EtherType: IPv4.
+const ETHERTYPE_IPV4: u16 = 0x0800;
+/// EtherType: IPv6.
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+
+/// IP protocol number: ICMP.
+const IPPROTO_ICMP: u8 = 1;
+/// IP protocol number: TCP.
+const IPPROTO_TCP: u8 = 6;
+/// IP protocol number: UDP.
+const IPPROTO_UDP: u8 = 17;
+
+/// The length in bytes of an Ethernet header (destination MAC, source MAC, EtherType).
+const ETH_HEADER_LEN: usize = 14;
+/// The fixed length in bytes of an IPv6 header.
+const IPV6_HEADER_LEN: usize = 40;
+
+/// A handler registered with [`register_transport`] to receive every inbound packet for a given
+/// IP protocol number, so [`dispatch_transport`] can deliver to it without hard-coding which
+/// transport protocols exist.
+pub type TransportHandler = for<'r> fn(BuffList<'r>) -> Result<(), Errno>;
+
+/// The transport-layer handlers currently registered, keyed by IP protocol number.
+static TRANSPORT_HANDLERS: Mutex<Vec<(u8, TransportHandler)>> = Mutex::new(Vec::new());
+
+/// Registers `handler` to receive every inbound packet whose IP protocol number is `protocol`,
+/// replacing any handler already registered for it.
+///
+/// An `icmp`/`tcp`/`udp` `Layer` implementor calls this once at init, the same way
+/// [`register_packet_socket`] lets an `AF_PACKET` socket register interest in raw frames.
+pub fn register_transport(protocol: u8, handler: TransportHandler) -> Result<(), Errno> {
+	let mut handlers = TRANSPORT_HANDLERS.lock();
+	if let Some(slot) = handlers.iter_mut().find(|(p, _)| *p == protocol) {
+		slot.1 = handler;
+		return Ok(());
+	}
+	handlers.push((protocol, handler))
+}
+
+/// Hands `payload` to the layer registered for IP protocol number `protocol` via
+/// [`register_transport`].
+///
+/// NOTE: this tree has no concrete `icmp`/`tcp`/`udp` `Layer` implementor yet, so nothing is ever
+/// registered and every protocol is dropped for now; once one exists, it registers itself here
+/// instead of this function hard-coding a match per protocol.
+fn dispatch_transport(protocol: u8, payload: BuffList) -> Result<(), Errno> {
+	let handlers = TRANSPORT_HANDLERS.lock();
+	match handlers.iter().find(|(p, _)| *p == protocol) {
+		Some((_, handler)) => handler(payload),
+
+		// No transport layer registered for this protocol: nothing in this stack understands it,
+		// drop silently.
+		None => Ok(()),
+	}
+}
+
+/// Receives an IPv4 packet, peeling its header off to find the next protocol and hand the
+/// remainder to [`dispatch_transport`].
+fn receive_ipv4(buff: BuffList) -> Result<(), Errno> {
+	let hdr = buff.b;
+	if hdr.len() < 20 {
+		// Truncated header: drop.
+		return Ok(());
+	}
+
+	let ihl = (hdr[0] & 0x0f) as usize * 4;
+	if hdr.len() < ihl {
+		return Ok(());
+	}
+	let protocol = hdr[9];
+
+	dispatch_transport(protocol, buff.skip_front(ihl))
+}
+
+/// Receives an IPv6 packet, peeling its header off to find the next header and hand the
+/// remainder to [`dispatch_transport`].
+fn receive_ipv6(buff: BuffList) -> Result<(), Errno> {
+	let hdr = buff.b;
+	if hdr.len() < IPV6_HEADER_LEN {
+		// Truncated header: drop.
+		return Ok(());
+	}
+	let next_header = hdr[6];
+
+	dispatch_transport(next_header, buff.skip_front(IPV6_HEADER_LEN))
+}
+
+/// Receives a raw frame `raw`, read from `iface`, and pushes it up the layer stack: link -> IP ->
+/// ICMP/TCP/UDP.
+///
+/// The next handler at each step is chosen from the EtherType (link -> IP) or IP protocol number
+/// (IP -> transport) field, mirroring how [`Layer::transmit`] threads a packet down through the
+/// stack to build it.
+pub fn receive(iface: &dyn Interface, raw: &[u8]) -> Result<(), Errno> {
+	if raw.len() < ETH_HEADER_LEN {
+		// Truncated frame: drop.
+		return Ok(());
+	}
+
+	deliver_to_packet_sockets(iface.get_name(), raw);
+
+	let ethertype = u16::from_be_bytes([raw[12], raw[13]]);
+	let payload = BuffList::from(raw).skip_front(ETH_HEADER_LEN);
+
+	match ethertype {
+		ETHERTYPE_IPV4 => receive_ipv4(payload),
+		ETHERTYPE_IPV6 => receive_ipv6(payload),
+
+		// Unknown EtherType: nothing in this stack understands it, drop silently.
+		_ => Ok(()),
+	}
+}
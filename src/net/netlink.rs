@@ -0,0 +1,410 @@
+//! `NETLINK_ROUTE` is the netlink family userspace tools such as `ip route`/`ip addr` use to
+//! manage the routing table and interface addresses at runtime.
+//!
+//! A request is a stream of messages, each an `nlmsghdr` (`len`, `type`, `flags`, `seq`, `pid`,
+//! all little-endian `u32`/`u16`) followed by a family-specific payload padded up to a multiple
+//! of `NLMSG_ALIGNTO` (4) bytes. The payload itself starts with a fixed-size struct (`ifaddrmsg`
+//! for address messages, `rtmsg` for route messages) followed by a sequence of `rtattr` TLVs,
+//! each similarly 4-byte aligned.
+//!
+//! This module implements [`process_request`], which parses such a stream against [`INTERFACES`]
+//! and [`ROUTING_TABLE`] and returns the reply stream (acks, or for a dump request, the matching
+//! `RTM_NEW*` messages terminated by `NLMSG_DONE`).
+//!
+//! NOTE: nothing calls [`process_request`]: `crate::file::buffer::socket::Socket` has no
+//! `NETLINK_ROUTE` mode, its `read`/`write` only carry frames of `AF_PACKET`/`SOCK_RAW` sockets
+//! and fail with `ENOTTY` otherwise.
+
+use super::Address;
+use super::BindAddress;
+use super::Route;
+use super::INTERFACES;
+use super::ROUTING_TABLE;
+use crate::errno::Errno;
+use crate::util::container::string::String;
+use crate::util::container::vec::Vec;
+
+/// Address family: IPv4.
+const AF_INET: u8 = 2;
+/// Address family: IPv6.
+const AF_INET6: u8 = 10;
+
+/// Message type: a new address was added to an interface.
+const RTM_NEWADDR: u16 = 20;
+/// Message type: an address was removed from an interface.
+const RTM_DELADDR: u16 = 21;
+/// Message type: a new route was added.
+const RTM_NEWROUTE: u16 = 24;
+/// Message type: a route was removed.
+const RTM_DELROUTE: u16 = 25;
+/// Message type: a route lookup/dump request.
+const RTM_GETROUTE: u16 = 26;
+/// Message type: an error, or an ack if the embedded error code is zero.
+const NLMSG_ERROR: u16 = 2;
+/// Message type: marks the end of a dump.
+const NLMSG_DONE: u16 = 3;
+
+/// Request flag: the sender wants an ack for this message.
+const NLM_F_ACK: u16 = 0x4;
+/// Reply flag: more messages in this dump follow.
+const NLM_F_MULTI: u16 = 0x2;
+/// Request flag (`NLM_F_ROOT`): return the whole table.
+const NLM_F_ROOT: u16 = 0x100;
+/// Request flag (`NLM_F_MATCH`): return all entries matching the given criteria.
+const NLM_F_MATCH: u16 = 0x200;
+/// Request flag combination meaning "dump the whole table".
+const NLM_F_DUMP: u16 = NLM_F_ROOT | NLM_F_MATCH;
+
+/// `rtattr` type: the interface address (`ifaddrmsg`).
+const IFA_ADDRESS: u16 = 1;
+/// `rtattr` type: the local address, preferred over `IFA_ADDRESS` when both are present.
+const IFA_LOCAL: u16 = 2;
+
+/// `rtattr` type: the route's destination (`rtmsg`).
+const RTA_DST: u16 = 1;
+/// `rtattr` type: the output interface index.
+const RTA_OIF: u16 = 4;
+/// `rtattr` type: the route's gateway.
+const RTA_GATEWAY: u16 = 5;
+/// `rtattr` type: the route's metric.
+const RTA_PRIORITY: u16 = 6;
+
+/// The alignment, in bytes, of every netlink message and attribute.
+const NLMSG_ALIGNTO: usize = 4;
+
+/// Rounds `len` up to the next multiple of [`NLMSG_ALIGNTO`].
+fn nlmsg_align(len: usize) -> usize {
+	(len + NLMSG_ALIGNTO - 1) & !(NLMSG_ALIGNTO - 1)
+}
+
+/// Appends `bytes` to `out`.
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) -> Result<(), Errno> {
+	for &b in bytes {
+		out.push(b)?;
+	}
+
+	Ok(())
+}
+
+/// Appends an `nlmsghdr` followed by `payload` (padded to [`NLMSG_ALIGNTO`]) to `out`.
+fn push_msg(
+	out: &mut Vec<u8>,
+	type_: u16,
+	flags: u16,
+	seq: u32,
+	pid: u32,
+	payload: &[u8],
+) -> Result<(), Errno> {
+	let len = 16 + payload.len();
+
+	push_bytes(out, &(len as u32).to_le_bytes())?;
+	push_bytes(out, &type_.to_le_bytes())?;
+	push_bytes(out, &flags.to_le_bytes())?;
+	push_bytes(out, &seq.to_le_bytes())?;
+	push_bytes(out, &pid.to_le_bytes())?;
+	push_bytes(out, payload)?;
+
+	for _ in 0..(nlmsg_align(len) - len) {
+		out.push(0)?;
+	}
+
+	Ok(())
+}
+
+/// Appends an `rtattr` of the given `type_` carrying `payload` (padded to [`NLMSG_ALIGNTO`]) to
+/// `out`.
+fn push_attr(out: &mut Vec<u8>, type_: u16, payload: &[u8]) -> Result<(), Errno> {
+	let len = 4 + payload.len();
+
+	push_bytes(out, &(len as u16).to_le_bytes())?;
+	push_bytes(out, &type_.to_le_bytes())?;
+	push_bytes(out, payload)?;
+
+	for _ in 0..(nlmsg_align(len) - len) {
+		out.push(0)?;
+	}
+
+	Ok(())
+}
+
+/// Parses the sequence of `rtattr` TLVs in `buf`, returning each attribute's type and payload.
+fn parse_attrs(buf: &[u8]) -> Result<Vec<(u16, &[u8])>, Errno> {
+	let mut attrs = Vec::new();
+
+	let mut off = 0;
+	while off + 4 <= buf.len() {
+		let len = u16::from_le_bytes([buf[off], buf[off + 1]]) as usize;
+		let type_ = u16::from_le_bytes([buf[off + 2], buf[off + 3]]);
+
+		if len < 4 || off + len > buf.len() {
+			break;
+		}
+
+		attrs.push((type_, &buf[(off + 4)..(off + len)]))?;
+		off += nlmsg_align(len);
+	}
+
+	Ok(attrs)
+}
+
+/// Returns the raw bytes of `addr`.
+fn addr_bytes(addr: &Address) -> &[u8] {
+	match addr {
+		Address::IPv4(b) => b,
+		Address::IPv6(b) => b,
+	}
+}
+
+/// Interprets `bytes` as an [`Address`] of the given family, if it is the right length for it.
+fn addr_from_bytes(family: u8, bytes: &[u8]) -> Option<Address> {
+	match family {
+		AF_INET => Some(Address::IPv4(bytes.try_into().ok()?)),
+		AF_INET6 => Some(Address::IPv6(bytes.try_into().ok()?)),
+		_ => None,
+	}
+}
+
+/// Returns the 1-based index of the interface named `name`, or `None` if it isn't registered.
+///
+/// This tree has no dedicated ifindex field on [`super::Interface`], so the interface's position
+/// in [`INTERFACES`] is used as its index, as rtnetlink tools expect one to exist.
+fn find_ifindex(name: &[u8]) -> Option<u32> {
+	let interfaces = INTERFACES.lock();
+
+	interfaces
+		.iter()
+		.position(|iface| iface.get_name() == name)
+		.map(|pos| (pos + 1) as u32)
+}
+
+/// Handles an `RTM_NEWADDR` message, binding the address carried by `IFA_LOCAL`/`IFA_ADDRESS` to
+/// the interface designated by the `ifaddrmsg`'s `index` field.
+fn handle_newaddr(payload: &[u8]) -> Result<(), Errno> {
+	if payload.len() < 8 {
+		return Err(errno!(EINVAL));
+	}
+	let family = payload[0];
+	let prefixlen = payload[1];
+	let index = u32::from_le_bytes(payload[4..8].try_into().unwrap());
+
+	let attrs = parse_attrs(&payload[8..])?;
+	let addr_bytes = attrs
+		.iter()
+		.find(|(t, _)| *t == IFA_LOCAL)
+		.or_else(|| attrs.iter().find(|(t, _)| *t == IFA_ADDRESS))
+		.map(|(_, v)| *v)
+		.ok_or(errno!(EINVAL))?;
+	let addr = addr_from_bytes(family, addr_bytes).ok_or(errno!(EINVAL))?;
+
+	let pos = (index as usize).checked_sub(1).ok_or(errno!(ENODEV))?;
+	let mut interfaces = INTERFACES.lock();
+	let iface = interfaces.get_mut(pos).ok_or(errno!(ENODEV))?;
+
+	iface.get_addresses_mut().push(BindAddress {
+		addr,
+		subnet_mask: prefixlen,
+	})?;
+
+	Ok(())
+}
+
+/// Handles an `RTM_DELADDR` message, removing the address carried by `IFA_LOCAL`/`IFA_ADDRESS`
+/// from the interface designated by the `ifaddrmsg`'s `index` field.
+fn handle_deladdr(payload: &[u8]) -> Result<(), Errno> {
+	if payload.len() < 8 {
+		return Err(errno!(EINVAL));
+	}
+	let family = payload[0];
+	let index = u32::from_le_bytes(payload[4..8].try_into().unwrap());
+
+	let attrs = parse_attrs(&payload[8..])?;
+	let addr_bytes = attrs
+		.iter()
+		.find(|(t, _)| *t == IFA_LOCAL)
+		.or_else(|| attrs.iter().find(|(t, _)| *t == IFA_ADDRESS))
+		.map(|(_, v)| *v)
+		.ok_or(errno!(EINVAL))?;
+	let addr = addr_from_bytes(family, addr_bytes).ok_or(errno!(EINVAL))?;
+
+	let pos = (index as usize).checked_sub(1).ok_or(errno!(ENODEV))?;
+	let mut interfaces = INTERFACES.lock();
+	let iface = interfaces.get_mut(pos).ok_or(errno!(ENODEV))?;
+
+	let addrs = iface.get_addresses_mut();
+	let i = addrs
+		.iter()
+		.position(|a| a.addr == addr)
+		.ok_or(errno!(EADDRNOTAVAIL))?;
+	addrs.remove(i);
+
+	Ok(())
+}
+
+/// Parses an `rtmsg` payload shared by the `RTM_*ROUTE` messages, returning the destination
+/// (if any), the gateway, the output interface's name and the metric.
+fn parse_route(payload: &[u8]) -> Result<(Option<BindAddress>, Address, String, u32), Errno> {
+	if payload.len() < 12 {
+		return Err(errno!(EINVAL));
+	}
+	let family = payload[0];
+	let dst_len = payload[1];
+
+	let attrs = parse_attrs(&payload[12..])?;
+
+	let dst = attrs
+		.iter()
+		.find(|(t, _)| *t == RTA_DST)
+		.and_then(|(_, v)| addr_from_bytes(family, v))
+		.map(|addr| BindAddress {
+			addr,
+			subnet_mask: dst_len,
+		});
+
+	let gateway = attrs
+		.iter()
+		.find(|(t, _)| *t == RTA_GATEWAY)
+		.and_then(|(_, v)| addr_from_bytes(family, v))
+		.ok_or(errno!(EINVAL))?;
+
+	let oif = attrs
+		.iter()
+		.find(|(t, _)| *t == RTA_OIF)
+		.and_then(|(_, v)| Some(u32::from_le_bytes((*v).try_into().ok()?)))
+		.ok_or(errno!(EINVAL))?;
+
+	let metric = attrs
+		.iter()
+		.find(|(t, _)| *t == RTA_PRIORITY)
+		.and_then(|(_, v)| Some(u32::from_le_bytes((*v).try_into().ok()?)))
+		.unwrap_or(0);
+
+	let iface_name = {
+		let interfaces = INTERFACES.lock();
+		let pos = (oif as usize).checked_sub(1).ok_or(errno!(ENODEV))?;
+		let iface = interfaces.get(pos).ok_or(errno!(ENODEV))?;
+		String::try_from(iface.get_name())?
+	};
+
+	Ok((dst, gateway, iface_name, metric))
+}
+
+/// Handles an `RTM_NEWROUTE` message, inserting the described entry into [`ROUTING_TABLE`].
+fn handle_newroute(payload: &[u8]) -> Result<(), Errno> {
+	let (dst, gateway, iface, metric) = parse_route(payload)?;
+	ROUTING_TABLE.lock().push(Route::new(dst, iface, gateway, metric))?;
+
+	Ok(())
+}
+
+/// Handles an `RTM_DELROUTE` message, removing the matching entry (by destination and output
+/// interface) from [`ROUTING_TABLE`].
+fn handle_delroute(payload: &[u8]) -> Result<(), Errno> {
+	let (dst, _gateway, iface, _metric) = parse_route(payload)?;
+
+	let mut table = ROUTING_TABLE.lock();
+	let i = table
+		.iter()
+		.position(|route| {
+			route.get_iface().as_bytes() == iface.as_bytes()
+				&& match (&dst, route.get_dst()) {
+					(Some(a), Some(b)) => a.addr == b.addr && a.subnet_mask == b.subnet_mask,
+					(None, None) => true,
+					_ => false,
+				}
+		})
+		.ok_or(errno!(ESRCH))?;
+	table.remove(i);
+
+	Ok(())
+}
+
+/// Serializes the whole of [`ROUTING_TABLE`] as a stream of `RTM_NEWROUTE` messages terminated
+/// by `NLMSG_DONE`, as a reply to an `RTM_GETROUTE` dump request.
+fn dump_routes(out: &mut Vec<u8>, seq: u32, pid: u32) -> Result<(), Errno> {
+	let table = ROUTING_TABLE.lock();
+
+	for route in table.iter() {
+		let family = match route.get_gateway() {
+			Address::IPv4(_) => AF_INET,
+			Address::IPv6(_) => AF_INET6,
+		};
+		let dst_len = route.get_dst().map(|d| d.subnet_mask).unwrap_or(0);
+
+		let mut payload = Vec::new();
+		// family, dst_len, src_len, tos, table, protocol, scope, type_
+		push_bytes(&mut payload, &[family, dst_len, 0, 0, 0, 0, 0, 0])?;
+		push_bytes(&mut payload, &0u32.to_le_bytes())?; // flags
+
+		if let Some(dst) = route.get_dst() {
+			push_attr(&mut payload, RTA_DST, addr_bytes(&dst.addr))?;
+		}
+		push_attr(&mut payload, RTA_GATEWAY, addr_bytes(route.get_gateway()))?;
+		push_attr(&mut payload, RTA_PRIORITY, &route.get_metric().to_le_bytes())?;
+		if let Some(oif) = find_ifindex(route.get_iface().as_bytes()) {
+			push_attr(&mut payload, RTA_OIF, &oif.to_le_bytes())?;
+		}
+
+		push_msg(out, RTM_NEWROUTE, NLM_F_MULTI, seq, pid, &payload)?;
+	}
+
+	push_msg(out, NLMSG_DONE, NLM_F_MULTI, seq, pid, &[])
+}
+
+/// Appends an ack (an `NLMSG_ERROR` message with a zero error code, echoing back `orig_hdr`, the
+/// original message's `nlmsghdr`) to `out`.
+fn push_ack(out: &mut Vec<u8>, seq: u32, pid: u32, orig_hdr: &[u8]) -> Result<(), Errno> {
+	let mut payload = Vec::new();
+	push_bytes(&mut payload, &0i32.to_le_bytes())?;
+	push_bytes(&mut payload, orig_hdr)?;
+
+	push_msg(out, NLMSG_ERROR, 0, seq, pid, &payload)
+}
+
+/// Processes a `NETLINK_ROUTE` request: a stream of one or more `nlmsghdr`-framed messages read
+/// from a socket's send buffer.
+///
+/// Returns the reply stream to hand back on the next read of the socket: an ack for every message
+/// with `NLM_F_ACK` set, or the dumped table terminated by `NLMSG_DONE` for a dump request.
+pub fn process_request(buf: &[u8]) -> Result<Vec<u8>, Errno> {
+	let mut response = Vec::new();
+
+	let mut off = 0;
+	while off + 16 <= buf.len() {
+		let msg_len = u32::from_le_bytes(buf[off..(off + 4)].try_into().unwrap()) as usize;
+		let msg_type = u16::from_le_bytes(buf[(off + 4)..(off + 6)].try_into().unwrap());
+		let msg_flags = u16::from_le_bytes(buf[(off + 6)..(off + 8)].try_into().unwrap());
+		let msg_seq = u32::from_le_bytes(buf[(off + 8)..(off + 12)].try_into().unwrap());
+		let msg_pid = u32::from_le_bytes(buf[(off + 12)..(off + 16)].try_into().unwrap());
+
+		if msg_len < 16 || off + msg_len > buf.len() {
+			break;
+		}
+		let payload = &buf[(off + 16)..(off + msg_len)];
+
+		if msg_type == RTM_GETROUTE {
+			if msg_flags & NLM_F_DUMP == 0 {
+				return Err(errno!(EOPNOTSUPP));
+			}
+			dump_routes(&mut response, msg_seq, msg_pid)?;
+
+			off += nlmsg_align(msg_len);
+			continue;
+		}
+
+		match msg_type {
+			RTM_NEWADDR => handle_newaddr(payload)?,
+			RTM_DELADDR => handle_deladdr(payload)?,
+			RTM_NEWROUTE => handle_newroute(payload)?,
+			RTM_DELROUTE => handle_delroute(payload)?,
+			_ => return Err(errno!(EOPNOTSUPP)),
+		}
+
+		if msg_flags & NLM_F_ACK != 0 {
+			push_ack(&mut response, msg_seq, msg_pid, &buf[off..(off + 16)])?;
+		}
+
+		off += nlmsg_align(msg_len);
+	}
+
+	Ok(response)
+}
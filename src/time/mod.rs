@@ -17,13 +17,20 @@ use unit::TimestampScale;
 pub trait ClockSource {
 	/// The name of the source.
 	fn get_name(&self) -> &str;
+	/// Returns the rating of the source: the higher, the more accurate and stable the source is
+	/// (for instance, a TSC-style source should outrank a PIT-style one).
+	///
+	/// `get` always picks the available source with the highest rating.
+	fn get_rating(&self) -> u32;
 	/// Returns the current timestamp in seconds.
 	/// `scale` specifies the scale of the returned timestamp.
 	fn get_time(&mut self, scale: TimestampScale) -> Timestamp;
 }
 
-// TODO Order by name to allow binary search
-/// Vector containing all the clock sources.
+/// Vector containing all the clock sources, kept sorted by descending rating (ties are broken by
+/// name, for a deterministic order) so that `get` can simply use the first entry, and to support
+/// the binary-search lookup the existing TODO anticipates.
+// TODO Binary search by name
 static CLOCK_SOURCES: Mutex<Vec<Box<dyn ClockSource>>> = Mutex::new(Vec::new());
 
 /// Returns a reference to the list of clock sources.
@@ -31,11 +38,24 @@ pub fn get_clock_sources() -> &'static Mutex<Vec<Box<dyn ClockSource>>> {
 	&CLOCK_SOURCES
 }
 
-/// Adds the new clock source to the clock sources list.
+/// Tells whether `a` must be ordered before `b` in `CLOCK_SOURCES`: `a` has a strictly higher
+/// rating, or the same rating with a name that sorts first.
+fn precedes(a: &dyn ClockSource, b: &dyn ClockSource) -> bool {
+	a.get_rating() > b.get_rating()
+		|| (a.get_rating() == b.get_rating() && a.get_name() < b.get_name())
+}
+
+/// Adds the new clock source to the clock sources list, inserting it in rating order.
 pub fn add_clock_source<T: 'static + ClockSource>(source: T) -> Result<(), Errno> {
 	let guard = CLOCK_SOURCES.lock();
 	let sources = guard.get_mut();
-	sources.push(Box::new(source)?)?;
+
+	let boxed = Box::new(source)?;
+	let index = sources.iter()
+		.position(|s| !precedes(s, &boxed))
+		.unwrap_or(sources.len());
+	sources.insert(index, boxed)?;
+
 	Ok(())
 }
 
@@ -53,18 +73,43 @@ pub fn remove_clock_source(name: &str) {
 	}
 }
 
+/// The last timestamp returned by `get` for each scale it has been called with, used to enforce
+/// monotonicity across a change of the preferred clock source or a wrap of the current one.
+static LAST_TIMESTAMPS: Mutex<Vec<(TimestampScale, Timestamp)>> = Mutex::new(Vec::new());
+
 /// Returns the current timestamp from the preferred clock source.
 /// `scale` specifies the scale of the returned timestamp.
 /// If no clock source is available, the function returns None.
+///
+/// The returned value never goes backwards relative to a previous call with the same `scale`,
+/// even if the preferred source changes or its underlying counter wraps.
 pub fn get(scale: TimestampScale) -> Option<Timestamp> {
-	let guard = CLOCK_SOURCES.lock();
-	let sources = guard.get_mut();
+	let raw = {
+		let guard = CLOCK_SOURCES.lock();
+		let sources = guard.get_mut();
 
-	if !sources.is_empty() {
-		let src = &mut sources[0]; // TODO Select the preferred source
-		Some(src.get_time(scale))
-	} else {
-		None
+		if sources.is_empty() {
+			return None;
+		}
+		// `CLOCK_SOURCES` is kept sorted by rating, so the preferred source is always first
+		sources[0].get_time(scale)
+	};
+
+	let guard = LAST_TIMESTAMPS.lock();
+	let last = guard.get_mut();
+
+	match last.iter_mut().find(|(s, _)| *s == scale) {
+		Some((_, prev)) => {
+			if raw > *prev {
+				*prev = raw;
+			}
+			Some(*prev)
+		}
+
+		None => {
+			last.push((scale, raw)).ok()?;
+			Some(raw)
+		}
 	}
 }
 
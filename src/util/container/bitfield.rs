@@ -6,6 +6,9 @@ use crate::util::container::vec::Vec;
 use crate::util::math::ceil_div;
 use crate::util::TryClone;
 
+/// The size in bytes of a word used for word-granular scans over a bitfield's data.
+const WORD_SIZE: usize = core::mem::size_of::<usize>();
+
 /// A bitfield is a data structure meant to contain only boolean values.
 ///
 /// The size of the bitfield is specified at initialization.
@@ -79,14 +82,49 @@ impl Bitfield {
 		}
 	}
 
+	/// Builds the word of `WORD_SIZE` bytes starting at byte offset `byte_off` in `self.data`,
+	/// zero-extending if fewer than `WORD_SIZE` bytes remain.
+	fn word_at(&self, byte_off: usize) -> usize {
+		let mut buf = [0u8; WORD_SIZE];
+		let remaining = &self.data[byte_off..];
+		let n = remaining.len().min(WORD_SIZE);
+		buf[..n].copy_from_slice(&remaining[..n]);
+		usize::from_le_bytes(buf)
+	}
+
+	/// Returns a mask set on every bit of the word starting at byte offset `byte_off` that is
+	/// still within `self.len`, so out-of-range padding bits can be excluded from a scan.
+	fn tail_mask(&self, byte_off: usize) -> usize {
+		let bits_remaining = self.len.saturating_sub(byte_off * u8::BITS as usize);
+		let word_bits = WORD_SIZE * u8::BITS as usize;
+		if bits_remaining >= word_bits {
+			usize::MAX
+		} else {
+			(1 << bits_remaining) - 1
+		}
+	}
+
+	/// Scans `self.data` a word at a time for the first bit matching `clear`.
+	fn find_bit(&self, clear: bool) -> Option<usize> {
+		let mut byte_off = 0;
+		while byte_off < self.data.len() {
+			let word = self.word_at(byte_off);
+			let candidates = if clear { !word } else { word } & self.tail_mask(byte_off);
+			if candidates != 0 {
+				return Some(byte_off * u8::BITS as usize + candidates.trailing_zeros() as usize);
+			}
+			byte_off += WORD_SIZE;
+		}
+		None
+	}
+
 	/// Finds a set bit.
 	///
 	/// The function returns the offset to the bit.
 	///
 	/// If none is found, the function returns `None`.
 	pub fn find_set(&self) -> Option<usize> {
-		// TODO optimize (using mask)
-		(0..self.len).find(|i| self.is_set(*i))
+		self.find_bit(false)
 	}
 
 	/// Finds a clear bit.
@@ -95,8 +133,72 @@ impl Bitfield {
 	///
 	/// If none is found, the function returns `None`.
 	pub fn find_clear(&self) -> Option<usize> {
-		// TODO optimize (using mask)
-		(0..self.len).find(|i| !self.is_set(*i))
+		self.find_bit(true)
+	}
+
+	/// Sets every bit in the range `[start, start + len)`.
+	pub fn set_range(&mut self, start: usize, len: usize) {
+		self.fill_range(start, len, true);
+	}
+
+	/// Clears every bit in the range `[start, start + len)`.
+	pub fn clear_range(&mut self, start: usize, len: usize) {
+		self.fill_range(start, len, false);
+	}
+
+	/// Sets or clears (depending on `value`) every bit in the range `[start, start + len)`,
+	/// a bit at a time for the leading and trailing partial bytes and a byte (word) at a time
+	/// for every whole byte in between.
+	fn fill_range(&mut self, start: usize, len: usize, value: bool) {
+		debug_assert!(start + len <= self.len);
+
+		if len == 0 {
+			return;
+		}
+
+		let bits = u8::BITS as usize;
+		let end = start + len;
+		let start_byte = start / bits;
+		let end_byte = end / bits;
+
+		if start_byte == end_byte {
+			for i in start..end {
+				if value {
+					self.set(i);
+				} else {
+					self.clear(i);
+				}
+			}
+			return;
+		}
+
+		for i in start..(start_byte + 1) * bits {
+			if value {
+				self.set(i);
+			} else {
+				self.clear(i);
+			}
+		}
+		self.data[(start_byte + 1)..end_byte].fill(if value { 0xff } else { 0 });
+		for i in (end_byte * bits)..end {
+			if value {
+				self.set(i);
+			} else {
+				self.clear(i);
+			}
+		}
+	}
+
+	/// Returns the number of bits set in the bitfield.
+	pub fn count_ones(&self) -> usize {
+		let mut count = 0;
+		let mut byte_off = 0;
+		while byte_off < self.data.len() {
+			let word = self.word_at(byte_off) & self.tail_mask(byte_off);
+			count += word.count_ones() as usize;
+			byte_off += WORD_SIZE;
+		}
+		count
 	}
 
 	/// Clears every elements in the bitfield.
@@ -194,5 +296,43 @@ mod test {
 		}
 	}
 
+	#[test_case]
+	fn bitfield_find0() {
+		let mut bitfield = Bitfield::new(200).unwrap();
+		assert_eq!(bitfield.find_set(), None);
+		assert_eq!(bitfield.find_clear(), Some(0));
+
+		bitfield.set(130);
+		assert_eq!(bitfield.find_set(), Some(130));
+		assert_eq!(bitfield.find_clear(), Some(0));
+
+		bitfield.set_all();
+		assert_eq!(bitfield.find_clear(), None);
+		assert_eq!(bitfield.find_set(), Some(0));
+	}
+
+	#[test_case]
+	fn bitfield_range0() {
+		let mut bitfield = Bitfield::new(100).unwrap();
+		bitfield.set_range(10, 50);
+
+		for i in 0..10 {
+			assert!(!bitfield.is_set(i));
+		}
+		for i in 10..60 {
+			assert!(bitfield.is_set(i));
+		}
+		for i in 60..100 {
+			assert!(!bitfield.is_set(i));
+		}
+		assert_eq!(bitfield.count_ones(), 50);
+
+		bitfield.clear_range(20, 10);
+		for i in 20..30 {
+			assert!(!bitfield.is_set(i));
+		}
+		assert_eq!(bitfield.count_ones(), 40);
+	}
+
 	// TODO Write more tests
 }
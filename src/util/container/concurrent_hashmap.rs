@@ -0,0 +1,198 @@
+//! A concurrent hashmap shards its entries across several independently-locked [`HashMap`]s so
+//! that concurrent accesses to different shards (or even the same shard, for readers) never block
+//! each other.
+//!
+//! Nothing in the tree constructs a [`ConcurrentHashMap`]; in particular, the file cache does not
+//! use it.
+
+use super::hashmap::DefaultHashBuilder;
+use super::hashmap::HashMap;
+use super::vec::Vec;
+use crate::errno::AllocResult;
+use crate::util::lock::rwlock::RwLock;
+use crate::util::lock::rwlock::RwLockReadGuard;
+use crate::util::lock::rwlock::RwLockWriteGuard;
+use core::borrow::Borrow;
+use core::hash::BuildHasher;
+use core::hash::Hash;
+use core::hash::Hasher;
+
+/// The default number of shards. Must be a power of two.
+const DEFAULT_SHARDS_COUNT: usize = 16;
+
+/// A hashmap sharding its entries across several independently-locked buckets.
+///
+/// `S` is the [`BuildHasher`] used both to pick a key's shard and to hash it within that shard.
+pub struct ConcurrentHashMap<K: Eq + Hash, V, S = DefaultHashBuilder> {
+	/// The shards, each an independent hashmap guarded by its own reader/writer lock.
+	shards: Vec<RwLock<HashMap<K, V, S>>>,
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher + Default> ConcurrentHashMap<K, V, S> {
+	/// Creates a new instance with the default number of shards.
+	pub fn new() -> AllocResult<Self> {
+		Self::with_shards_count(DEFAULT_SHARDS_COUNT)
+	}
+
+	/// Creates a new instance with `shards_count` shards, which must be a power of two.
+	pub fn with_shards_count(shards_count: usize) -> AllocResult<Self> {
+		debug_assert!(shards_count.is_power_of_two());
+
+		let mut shards = Vec::with_capacity(shards_count)?;
+		for _ in 0..shards_count {
+			shards.push(RwLock::new(HashMap::new()))?;
+		}
+
+		Ok(Self {
+			shards,
+		})
+	}
+
+	/// Returns the index of the shard that stores (or would store) key `k`.
+	fn shard_index<Q: Hash + ?Sized>(&self, k: &Q) -> usize {
+		let mut hasher = S::default().build_hasher();
+		k.hash(&mut hasher);
+
+		(hasher.finish() as usize) & (self.shards.len() - 1)
+	}
+
+	/// Locks the shard storing key `k` for reading.
+	fn read_shard<Q: Hash + ?Sized>(&self, k: &Q) -> RwLockReadGuard<HashMap<K, V, S>> {
+		self.shards[self.shard_index(k)].read()
+	}
+
+	/// Locks the shard storing key `k` for writing.
+	fn write_shard<Q: Hash + ?Sized>(&self, k: &Q) -> RwLockWriteGuard<HashMap<K, V, S>> {
+		self.shards[self.shard_index(k)].write()
+	}
+
+	/// Returns a guard giving read-only access to the value associated with key `k`, or `None` if
+	/// the key isn't present.
+	///
+	/// The guard holds the shard's read lock for its whole lifetime: other readers of the same or
+	/// other shards are not blocked, but writers to this shard are.
+	pub fn get<Q: ?Sized>(&self, k: &Q) -> Option<ReadRef<K, V, S>>
+	where
+		K: Borrow<Q>,
+		Q: Hash + Eq,
+	{
+		let guard = self.read_shard(k);
+		let value: *const V = guard.get().get(k)?;
+
+		Some(ReadRef {
+			_guard: guard,
+			value,
+		})
+	}
+
+	/// Returns a guard giving mutable access to the value associated with key `k`, or `None` if
+	/// the key isn't present.
+	///
+	/// The guard holds the shard's write lock for its whole lifetime, blocking every other access
+	/// to the shard.
+	pub fn get_mut<Q: ?Sized>(&self, k: &Q) -> Option<WriteRef<K, V, S>>
+	where
+		K: Borrow<Q>,
+		Q: Hash + Eq,
+	{
+		let mut guard = self.write_shard(k);
+		let value: *mut V = guard.get_mut().get_mut(k)?;
+
+		Some(WriteRef {
+			_guard: guard,
+			value,
+		})
+	}
+
+	/// Inserts a new element into the map.
+	///
+	/// If the key was already present, the function returns the previous value.
+	pub fn insert(&self, k: K, v: V) -> AllocResult<Option<V>> {
+		self.write_shard(&k).get_mut().insert(k, v)
+	}
+
+	/// Removes an element from the map.
+	///
+	/// If the key was present, the function returns the previous value.
+	pub fn remove<Q: ?Sized>(&self, k: &Q) -> Option<V>
+	where
+		K: Borrow<Q>,
+		Q: Hash + Eq,
+	{
+		self.write_shard(k).get_mut().remove(k)
+	}
+
+	/// Grows the map to `new_shards_count` shards (which must be a power of two), redistributing
+	/// every entry.
+	///
+	/// This briefly takes every shard's write lock (in order, to avoid deadlocking against a
+	/// concurrent call taking the same locks) so no accessor ever observes a partially migrated
+	/// table.
+	pub fn grow(&mut self, new_shards_count: usize) -> AllocResult<()>
+	where
+		S: Default,
+	{
+		debug_assert!(new_shards_count.is_power_of_two());
+
+		let mut new_shards = Vec::with_capacity(new_shards_count)?;
+		for _ in 0..new_shards_count {
+			new_shards.push(RwLock::new(HashMap::new()))?;
+		}
+
+		for shard in self.shards.iter() {
+			let mut guard = shard.write();
+			let old = core::mem::replace(guard.get_mut(), HashMap::new());
+
+			for (k, v) in old.into_iter() {
+				let mut hasher = S::default().build_hasher();
+				k.hash(&mut hasher);
+				let idx = (hasher.finish() as usize) & (new_shards_count - 1);
+
+				new_shards[idx].write().get_mut().insert(k, v)?;
+			}
+		}
+
+		self.shards = new_shards;
+		Ok(())
+	}
+}
+
+/// An RAII guard giving read-only access to a value stored in a [`ConcurrentHashMap`], holding
+/// the owning shard's read lock for its lifetime.
+pub struct ReadRef<'m, K: Eq + Hash, V, S> {
+	/// The shard's read guard, kept alive for as long as `value` is borrowed.
+	_guard: RwLockReadGuard<'m, HashMap<K, V, S>>,
+	/// Pointer to the value inside of the shard, valid as long as `_guard` is held.
+	value: *const V,
+}
+
+impl<'m, K: Eq + Hash, V, S> core::ops::Deref for ReadRef<'m, K, V, S> {
+	type Target = V;
+
+	fn deref(&self) -> &V {
+		unsafe { &*self.value }
+	}
+}
+
+/// An RAII guard giving mutable access to a value stored in a [`ConcurrentHashMap`], holding the
+/// owning shard's write lock for its lifetime.
+pub struct WriteRef<'m, K: Eq + Hash, V, S> {
+	/// The shard's write guard, kept alive for as long as `value` is borrowed.
+	_guard: RwLockWriteGuard<'m, HashMap<K, V, S>>,
+	/// Pointer to the value inside of the shard, valid as long as `_guard` is held.
+	value: *mut V,
+}
+
+impl<'m, K: Eq + Hash, V, S> core::ops::Deref for WriteRef<'m, K, V, S> {
+	type Target = V;
+
+	fn deref(&self) -> &V {
+		unsafe { &*self.value }
+	}
+}
+
+impl<'m, K: Eq + Hash, V, S> core::ops::DerefMut for WriteRef<'m, K, V, S> {
+	fn deref_mut(&mut self) -> &mut V {
+		unsafe { &mut *self.value }
+	}
+}
@@ -0,0 +1,347 @@
+//! A disk map is a hash index stored on block storage rather than in heap memory, so a large
+//! key/value index (for example the inode or dentry lookup tables of a filesystem) can exceed the
+//! size of RAM and survives memory pressure.
+//!
+//! The layout is a flat array of `buckets_count` fixed-size buckets, `buckets_count` being a
+//! power of two, selected by the high bits of the key's hash. Within a bucket, collisions are
+//! resolved with bounded linear probing: a lookup gives up after `max_search` consecutive slots,
+//! and an insert that cannot find a free slot within that window fails, letting the caller decide
+//! to grow the index and rehash.
+
+use crate::device::storage::StorageInterface;
+use crate::errno::Errno;
+use core::hash::Hash;
+use core::hash::Hasher;
+use core::marker::PhantomData;
+use core::mem::size_of;
+
+/// The state of a slot within a bucket.
+#[repr(u8)]
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum SlotState {
+	/// The slot has never been used.
+	Free = 0,
+	/// The slot holds a valid entry.
+	Occupied = 1,
+	/// The slot previously held an entry that was removed.
+	///
+	/// Unlike [`Self::Free`], a tombstone does not stop a lookup's linear probe: another key
+	/// hashing to the same bucket may have been placed past it while it was still occupied, and
+	/// would otherwise become unreachable. An insert may still reuse a tombstone slot, the same
+	/// as a free one.
+	Tombstone = 2,
+}
+
+/// A fixed-size record stored on disk, holding a key, a value and the state of the slot.
+#[repr(C)]
+struct Slot<K: Copy, V: Copy> {
+	/// The state of the slot.
+	state: SlotState,
+	/// The slot's key. Only meaningful if `state` is [`SlotState::Occupied`].
+	key: K,
+	/// The slot's value. Only meaningful if `state` is [`SlotState::Occupied`].
+	value: V,
+}
+
+/// An error returned when an insertion into a [`DiskMap`] cannot be satisfied.
+#[derive(Debug)]
+pub enum InsertError {
+	/// No free slot could be found for the key within `max_search` probes.
+	///
+	/// The caller should grow the bucket count and rehash every entry.
+	IndexFull,
+	/// The underlying storage does not have enough space to hold the index.
+	DataFull,
+	/// An I/O error occurred while accessing storage.
+	IO(Errno),
+}
+
+impl From<Errno> for InsertError {
+	fn from(e: Errno) -> Self {
+		Self::IO(e)
+	}
+}
+
+/// A hash index stored on block storage, using a power-of-two number of fixed-size buckets and
+/// bounded linear probing within each bucket.
+///
+/// `K` and `V` must be `Copy` so that slots can be read and written directly to/from storage
+/// without per-entry allocation.
+pub struct DiskMap<K: Copy + Eq + Hash, V: Copy> {
+	/// The offset of the index's first bucket on `storage`, in bytes.
+	base_offset: u64,
+	/// The number of buckets. Always a power of two.
+	buckets_count: usize,
+	/// The number of slots per bucket.
+	bucket_size: usize,
+	/// The maximum number of consecutive slots scanned before giving up on a lookup or insert.
+	max_search: usize,
+
+	_key: PhantomData<K>,
+	_val: PhantomData<V>,
+}
+
+impl<K: Copy + Eq + Hash, V: Copy> DiskMap<K, V> {
+	/// Creates a new instance describing an index stored at `base_offset` on `storage`, made of
+	/// `buckets_count` buckets (a power of two) of `bucket_size` slots each.
+	///
+	/// `max_search` bounds the number of consecutive slots scanned within a bucket before a
+	/// lookup declares the key absent, or an insert declares the bucket full. It must not exceed
+	/// `bucket_size`.
+	///
+	/// The function does not perform any I/O: the region is expected to already be zeroed (every
+	/// slot's `state` is [`SlotState::Free`]) or to have been previously populated by this type.
+	pub fn new(
+		storage: &dyn StorageInterface,
+		base_offset: u64,
+		buckets_count: usize,
+		bucket_size: usize,
+		max_search: usize,
+	) -> Result<Self, InsertError> {
+		debug_assert!(buckets_count.is_power_of_two());
+		debug_assert!(max_search <= bucket_size);
+
+		let slot_size = size_of::<Slot<K, V>>() as u64;
+		let total_size = slot_size * (buckets_count * bucket_size) as u64;
+		if base_offset + total_size > storage.get_size() {
+			return Err(InsertError::DataFull);
+		}
+
+		Ok(Self {
+			base_offset,
+			buckets_count,
+			bucket_size,
+			max_search,
+
+			_key: PhantomData,
+			_val: PhantomData,
+		})
+	}
+
+	/// Returns the index of the bucket holding `key`, derived from the high bits of its hash.
+	fn bucket_of(&self, key: &K) -> usize {
+		let mut hasher = FxHasher::new();
+		key.hash(&mut hasher);
+
+		let hash = hasher.finish();
+		((hash >> (u64::BITS as usize - self.buckets_count.trailing_zeros() as usize)) as usize)
+			& (self.buckets_count - 1)
+	}
+
+	/// Returns the byte offset, on storage, of the `n`-th slot of bucket `bucket`.
+	fn slot_offset(&self, bucket: usize, n: usize) -> u64 {
+		let slot_size = size_of::<Slot<K, V>>() as u64;
+		let slot_index = bucket * self.bucket_size + n;
+
+		self.base_offset + slot_index as u64 * slot_size
+	}
+
+	/// Reads the slot at byte offset `offset` on `storage`.
+	fn read_slot(storage: &mut dyn StorageInterface, offset: u64) -> Result<Slot<K, V>, Errno> {
+		let mut slot = Slot::<K, V> {
+			state: SlotState::Free,
+			// Safe: overwritten by `read_bytes` right after, for `Occupied` slots only used once
+			// `state` has been checked.
+			key: unsafe { core::mem::zeroed() },
+			value: unsafe { core::mem::zeroed() },
+		};
+
+		let buf = unsafe {
+			core::slice::from_raw_parts_mut(
+				&mut slot as *mut _ as *mut u8,
+				size_of::<Slot<K, V>>(),
+			)
+		};
+		storage.read_bytes(buf, offset)?;
+
+		Ok(slot)
+	}
+
+	/// Writes `slot` at byte offset `offset` on `storage`.
+	fn write_slot(
+		storage: &mut dyn StorageInterface,
+		offset: u64,
+		slot: &Slot<K, V>,
+	) -> Result<(), Errno> {
+		let buf = unsafe {
+			core::slice::from_raw_parts(slot as *const _ as *const u8, size_of::<Slot<K, V>>())
+		};
+		storage.write_bytes(buf, offset)
+	}
+
+	/// Looks up `key` in the index, returning its associated value if present.
+	///
+	/// The lookup scans at most `max_search` consecutive slots of the key's bucket before
+	/// declaring the key absent.
+	pub fn get(&self, storage: &mut dyn StorageInterface, key: &K) -> Result<Option<V>, Errno>
+	where
+		K: PartialEq,
+	{
+		let bucket = self.bucket_of(key);
+
+		for n in 0..self.max_search {
+			let offset = self.slot_offset(bucket, n);
+			let slot = Self::read_slot(storage, offset)?;
+
+			match slot.state {
+				SlotState::Free => return Ok(None),
+				SlotState::Occupied if slot.key == *key => return Ok(Some(slot.value)),
+				SlotState::Occupied | SlotState::Tombstone => {}
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Inserts `key`/`value` into the index, overwriting any previous value associated with
+	/// `key`.
+	///
+	/// If no free or matching slot can be found for `key` within `max_search` probes, the
+	/// function returns [`InsertError::IndexFull`]: the caller should grow the bucket count and
+	/// rehash every entry before retrying.
+	pub fn insert(
+		&self,
+		storage: &mut dyn StorageInterface,
+		key: K,
+		value: V,
+	) -> Result<(), InsertError>
+	where
+		K: PartialEq,
+	{
+		let bucket = self.bucket_of(&key);
+		// The first reusable slot; `key` itself may still sit past a tombstone, so keep probing
+		let mut target = None;
+
+		for n in 0..self.max_search {
+			let offset = self.slot_offset(bucket, n);
+			let slot = Self::read_slot(storage, offset)?;
+
+			match slot.state {
+				SlotState::Occupied if slot.key == key => {
+					target = Some(offset);
+					break;
+				}
+				SlotState::Occupied => {}
+				SlotState::Tombstone => target = target.or(Some(offset)),
+				SlotState::Free => {
+					target = target.or(Some(offset));
+					break;
+				}
+			}
+		}
+
+		let offset = target.ok_or(InsertError::IndexFull)?;
+		let slot = Slot {
+			state: SlotState::Occupied,
+			key,
+			value,
+		};
+		Self::write_slot(storage, offset, &slot)?;
+
+		Ok(())
+	}
+
+	/// Removes `key` from the index, returning its associated value if it was present.
+	pub fn remove(&self, storage: &mut dyn StorageInterface, key: &K) -> Result<Option<V>, Errno>
+	where
+		K: PartialEq,
+	{
+		let bucket = self.bucket_of(key);
+
+		for n in 0..self.max_search {
+			let offset = self.slot_offset(bucket, n);
+			let slot = Self::read_slot(storage, offset)?;
+
+			match slot.state {
+				SlotState::Free => return Ok(None),
+				SlotState::Occupied if slot.key == *key => {
+					// Leave a tombstone rather than marking the slot `Free`: a later key hashing
+					// to this bucket may have been placed past it by linear probing, and a `Free`
+					// slot would stop `get`/`insert` from ever reaching it again.
+					let freed = Slot::<K, V> {
+						state: SlotState::Tombstone,
+						key: slot.key,
+						value: slot.value,
+					};
+					Self::write_slot(storage, offset, &freed)?;
+
+					return Ok(Some(slot.value));
+				}
+				SlotState::Occupied | SlotState::Tombstone => {}
+			}
+		}
+
+		Ok(None)
+	}
+
+	/// Calls `f` on every occupied entry currently stored in the index.
+	///
+	/// This is used by the caller to rehash every entry into a freshly grown [`DiskMap`] after an
+	/// [`InsertError::IndexFull`].
+	pub fn foreach<F: FnMut(K, V)>(
+		&self,
+		storage: &mut dyn StorageInterface,
+		mut f: F,
+	) -> Result<(), Errno> {
+		for bucket in 0..self.buckets_count {
+			for n in 0..self.bucket_size {
+				let offset = self.slot_offset(bucket, n);
+				let slot = Self::read_slot(storage, offset)?;
+
+				if slot.state == SlotState::Occupied {
+					f(slot.key, slot.value);
+				}
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// A small, fast, non-cryptographic hasher (the same family as rustc's `FxHash`), used to pick a
+/// key's bucket.
+///
+/// Unlike [`super::hashmap::FnvHasher`], which is tuned for small byte strings probed one byte at
+/// a time, this hasher is tuned for the fixed-size, word-sized keys typically used with
+/// [`DiskMap`].
+struct FxHasher {
+	hash: u64,
+}
+
+/// The multiplicative constant used by [`FxHasher`], chosen for its bit-mixing properties.
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+impl FxHasher {
+	/// Creates a new hasher.
+	fn new() -> Self {
+		Self {
+			hash: 0,
+		}
+	}
+
+	/// Mixes `word` into the hasher's state.
+	fn write_u64(&mut self, word: u64) {
+		self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+	}
+}
+
+impl Hasher for FxHasher {
+	fn write(&mut self, bytes: &[u8]) {
+		let mut chunks = bytes.chunks_exact(8);
+
+		for chunk in &mut chunks {
+			self.write_u64(u64::from_ne_bytes(chunk.try_into().unwrap()));
+		}
+
+		let rem = chunks.remainder();
+		if !rem.is_empty() {
+			let mut buf = [0u8; 8];
+			buf[..rem.len()].copy_from_slice(rem);
+			self.write_u64(u64::from_ne_bytes(buf));
+		}
+	}
+
+	fn finish(&self) -> u64 {
+		self.hash
+	}
+}
@@ -1,5 +1,12 @@
-//! A hashmap is a data structure that stores key/value pairs into buckets and
-//! uses the hash of the key to quickly get the bucket storing the value.
+//! A hashmap is a data structure that stores key/value pairs and uses the hash of the key to
+//! quickly get the value associated with it.
+//!
+//! The storage is a single open-addressed table (in the spirit of Google's SwissTable): a control
+//! byte array tracks, for each slot, whether it is empty, a tombstone, or occupied (in which case
+//! the byte also caches seven bits of the key's hash to avoid comparing most non-matching keys).
+//!
+//! The hashing algorithm is pluggable through [`BuildHasher`], following the standard library's
+//! `Hash`/`Hasher`/`BuildHasher` design. [`FnvHasher`] is used as the default.
 
 use super::vec::Vec;
 use crate::errno::AllocResult;
@@ -7,163 +14,104 @@ use crate::util::AllocError;
 use crate::util::TryClone;
 use core::borrow::Borrow;
 use core::fmt;
+use core::hash::BuildHasher;
+use core::hash::BuildHasherDefault;
 use core::hash::Hash;
 use core::hash::Hasher;
 use core::iter::FusedIterator;
 use core::iter::TrustedLen;
-use core::mem::size_of_val;
 use core::ops::Index;
 use core::ops::IndexMut;
 
-/// The default number of buckets in a hashmap.
-const DEFAULT_BUCKETS_COUNT: usize = 64;
+/// The default number of slots in a hashmap. Must be a power of two.
+const DEFAULT_CAPACITY: usize = 64;
 
-/// Bitwise XOR hasher.
-struct XORHasher {
-	/// The currently stored value.
-	value: u64,
-	/// The offset byte at which the next XOR operation shall be performed.
-	off: u8,
-}
+/// Control byte value for a slot that has never been occupied.
+const CTRL_EMPTY: u8 = 0xff;
+/// Control byte value for a slot that used to be occupied but was removed (a tombstone).
+const CTRL_DELETED: u8 = 0x80;
 
-impl XORHasher {
-	/// Creates a new instance.
-	pub fn new() -> Self {
-		Self {
-			value: 0,
-			off: 0,
-		}
+/// The numerator/denominator of the maximum load factor (occupied + tombstones vs capacity)
+/// before the table is grown.
+const MAX_LOAD_FACTOR_NUM: usize = 7;
+const MAX_LOAD_FACTOR_DENOM: usize = 8;
+
+/// The FNV offset basis, the initial state of [`FnvHasher`].
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+/// The FNV prime, used to mix each input byte into the state of [`FnvHasher`].
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// An implementation of the FNV-1a hash algorithm.
+///
+/// This is allocation-free and distributes keys far better than a naive XOR fold: unlike XOR
+/// folding, byte permutations of the same key and small integers do not collide into the same
+/// bucket, which matters since this map backs structures exposed to adversarial input (e.g. the
+/// file cache).
+#[derive(Clone)]
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+	fn default() -> Self {
+		Self(FNV_OFFSET_BASIS)
 	}
 }
 
-impl Hasher for XORHasher {
+impl Hasher for FnvHasher {
 	fn write(&mut self, bytes: &[u8]) {
 		for b in bytes {
-			self.value ^= (*b as u64) << (self.off * 8);
-			self.off = (self.off + 1) % size_of_val(&self.value) as u8;
+			self.0 ^= *b as u64;
+			self.0 = self.0.wrapping_mul(FNV_PRIME);
 		}
 	}
 
 	fn finish(&self) -> u64 {
-		self.value
+		self.0
 	}
 }
 
-/// A bucket is a list storing elements that match a given hash range.
-///
-/// Since hashing function have collisions, several elements can have the same
-/// hash.
-#[derive(Debug)]
-struct Bucket<K: Eq + Hash, V> {
-	/// The vector storing the key/value pairs.
-	elements: Vec<(K, V)>,
-}
-
-impl<K: Eq + Hash, V> Bucket<K, V> {
-	/// Creates a new instance.
-	fn new() -> Self {
-		Self {
-			elements: Vec::new(),
-		}
-	}
+/// The [`BuildHasher`] used by [`HashMap`] when none is specified explicitly.
+pub type DefaultHashBuilder = BuildHasherDefault<FnvHasher>;
 
-	/// Returns an immutable reference to the value with the given key `k`.
-	///
-	/// If the key isn't present, the function return `None`.
-	pub fn get<Q: ?Sized>(&self, k: &Q) -> Option<&V>
-	where
-		K: Borrow<Q>,
-		Q: Hash + Eq,
-	{
-		for i in 0..self.elements.len() {
-			if self.elements[i].0.borrow() == k {
-				return Some(&self.elements[i].1);
-			}
-		}
-
-		None
-	}
-
-	/// Returns a mutable reference to the value with the given key `k`.
-	///
-	/// If the key isn't present, the function return `None`.
-	pub fn get_mut<Q: ?Sized>(&mut self, k: &Q) -> Option<&mut V>
-	where
-		K: Borrow<Q>,
-		Q: Hash + Eq,
-	{
-		for i in 0..self.elements.len() {
-			if self.elements[i].0.borrow() == k {
-				return Some(&mut self.elements[i].1);
-			}
-		}
-
-		None
-	}
-
-	/// Inserts a new element into the bucket.
-	///
-	/// If the key was already present, the function returns the previous value.
-	pub fn insert(&mut self, k: K, v: V) -> AllocResult<Option<V>> {
-		let old = self.remove(&k);
-		self.elements.push((k, v))?;
-		Ok(old)
-	}
-
-	/// Removes an element from the bucket.
-	///
-	/// If the key was present, the function returns the value.
-	pub fn remove<Q: ?Sized>(&mut self, k: &Q) -> Option<V>
-	where
-		K: Borrow<Q>,
-		Q: Hash + Eq,
-	{
-		for i in 0..self.elements.len() {
-			if self.elements[i].0.borrow() == k {
-				return Some(self.elements.remove(i).1);
-			}
-		}
-
-		None
-	}
-}
-
-impl<K: Eq + Hash + TryClone<Error = E>, V: TryClone<Error = E>, E: From<AllocError>> TryClone
-	for Bucket<K, V>
-{
-	type Error = E;
-
-	fn try_clone(&self) -> Result<Self, Self::Error> {
-		let mut v = Vec::with_capacity(self.elements.len())?;
-		for (key, value) in self.elements.iter() {
-			v.push((key.try_clone()?, value.try_clone()?))?;
-		}
-
-		Ok(Self {
-			elements: v,
-		})
-	}
+/// Splits a full hash into its `h1` (bucket selection) and `h2` (control byte tag) components.
+///
+/// `h1` is the high bits of the hash, used to pick the probe's starting slot. `h2` is the low
+/// seven bits, stored in the control byte so most non-matching keys can be rejected without
+/// touching the key itself.
+#[inline]
+fn split_hash(hash: u64) -> (usize, u8) {
+	let h1 = (hash >> 7) as usize;
+	let h2 = (hash & 0x7f) as u8;
+	(h1, h2)
 }
 
 /// Structure representing a hashmap.
+///
+/// `S` is the [`BuildHasher`] used to hash keys, defaulting to [`DefaultHashBuilder`].
 #[derive(Debug)]
-pub struct HashMap<K: Eq + Hash, V> {
-	/// The number of buckets in the hashmap.
-	buckets_count: usize,
-	/// The vector containing buckets.
-	buckets: Vec<Bucket<K, V>>,
+pub struct HashMap<K: Eq + Hash, V, S = DefaultHashBuilder> {
+	/// The control byte for each slot.
+	ctrl: Vec<u8>,
+	/// The slots storing key/value pairs. Has the same length as `ctrl`.
+	slots: Vec<Option<(K, V)>>,
 
-	/// The number of elements in the container.
+	/// The number of occupied slots.
 	len: usize,
+	/// The number of occupied slots plus tombstones.
+	used: usize,
+
+	/// The hasher builder used to hash keys.
+	hash_builder: S,
 }
 
-impl<K: Eq + Hash, V> Default for HashMap<K, V> {
+impl<K: Eq + Hash, V, S: Default> Default for HashMap<K, V, S> {
 	fn default() -> Self {
-		Self::new()
+		Self::with_hasher(S::default())
 	}
 }
 
-impl<K: Eq + Hash, V, const N: usize> TryFrom<[(K, V); N]> for HashMap<K, V> {
+impl<K: Eq + Hash, V, S: Default + BuildHasher, const N: usize> TryFrom<[(K, V); N]>
+	for HashMap<K, V, S>
+{
 	type Error = AllocError;
 
 	fn try_from(arr: [(K, V); N]) -> Result<Self, Self::Error> {
@@ -176,24 +124,28 @@ impl<K: Eq + Hash, V, const N: usize> TryFrom<[(K, V); N]> for HashMap<K, V> {
 	}
 }
 
-impl<K: Eq + Hash, V> HashMap<K, V> {
-	/// Creates a new instance with the default number of buckets.
-	pub const fn new() -> Self {
-		Self {
-			buckets_count: DEFAULT_BUCKETS_COUNT,
-			buckets: Vec::new(),
-
-			len: 0,
-		}
+impl<K: Eq + Hash, V, S: Default> HashMap<K, V, S> {
+	/// Creates a new, empty instance using the default hasher builder.
+	///
+	/// No allocation is performed until the first insertion.
+	pub fn new() -> Self {
+		Self::with_hasher(S::default())
 	}
+}
 
-	/// Creates a new instance with the given number of buckets.
-	pub const fn with_buckets(buckets_count: usize) -> Self {
+impl<K: Eq + Hash, V, S> HashMap<K, V, S> {
+	/// Creates a new, empty instance using the given hasher builder `hash_builder`.
+	///
+	/// No allocation is performed until the first insertion.
+	pub const fn with_hasher(hash_builder: S) -> Self {
 		Self {
-			buckets_count,
-			buckets: Vec::new(),
+			ctrl: Vec::new(),
+			slots: Vec::new(),
 
 			len: 0,
+			used: 0,
+
+			hash_builder,
 		}
 	}
 
@@ -209,21 +161,191 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
 		self.len == 0
 	}
 
-	/// Returns the number of buckets.
+	/// Returns the number of slots currently allocated by the table.
 	#[inline]
 	pub fn get_buckets_count(&self) -> usize {
-		self.buckets_count
+		self.ctrl.len()
 	}
 
-	/// Returns the bucket index for the key `k`.
-	fn get_bucket_index<Q: ?Sized>(&self, k: &Q) -> usize
+	/// Creates an iterator of immutable references for the hash map.
+	#[inline]
+	pub fn iter(&self) -> Iter<K, V, S> {
+		Iter {
+			hm: self,
+
+			curr: 0,
+			i: 0,
+		}
+	}
+
+	/// Retains only the elements for which the given predicate returns `true`.
+	pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
+		for i in 0..self.ctrl.len() {
+			if self.ctrl[i] == CTRL_EMPTY || self.ctrl[i] == CTRL_DELETED {
+				continue;
+			}
+
+			let keep = {
+				let (k, v) = self.slots[i].as_mut().unwrap();
+				f(k, v)
+			};
+			if !keep {
+				self.ctrl[i] = CTRL_DELETED;
+				self.slots[i] = None;
+				self.len -= 1;
+			}
+		}
+	}
+
+	/// Drops all elements in the hash map.
+	pub fn clear(&mut self) {
+		self.ctrl.as_mut_slice().fill(CTRL_EMPTY);
+		for slot in self.slots.as_mut_slice() {
+			*slot = None;
+		}
+
+		self.len = 0;
+		self.used = 0;
+	}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> HashMap<K, V, S> {
+	/// Computes the hash of `k` using this map's hasher builder.
+	fn hash_of<Q: Hash + ?Sized>(&self, k: &Q) -> u64 {
+		let mut hasher = self.hash_builder.build_hasher();
+		k.hash(&mut hasher);
+		hasher.finish()
+	}
+
+	/// Allocates a new table of `capacity` slots (all empty). `capacity` must be a power of two.
+	fn new_table(capacity: usize) -> AllocResult<(Vec<u8>, Vec<Option<(K, V)>>)> {
+		let ctrl = crate::vec![CTRL_EMPTY; capacity]?;
+		let mut slots = Vec::with_capacity(capacity)?;
+		for _ in 0..capacity {
+			slots.push(None)?;
+		}
+		Ok((ctrl, slots))
+	}
+
+	/// Ensures the table has room for at least one more insertion, growing (and rehashing) it if
+	/// the load factor would otherwise be exceeded.
+	fn reserve_one(&mut self) -> AllocResult<()> {
+		let capacity = self.ctrl.len();
+		if capacity == 0 {
+			let (ctrl, slots) = Self::new_table(DEFAULT_CAPACITY)?;
+			self.ctrl = ctrl;
+			self.slots = slots;
+			return Ok(());
+		}
+
+		if (self.used + 1) * MAX_LOAD_FACTOR_DENOM > capacity * MAX_LOAD_FACTOR_NUM {
+			self.grow(capacity * 2)?;
+		}
+
+		Ok(())
+	}
+
+	/// Grows the table to `new_capacity` (a power of two) and reinserts every live entry.
+	fn grow(&mut self, new_capacity: usize) -> AllocResult<()> {
+		let (new_ctrl, new_slots) = Self::new_table(new_capacity);
+		let (new_ctrl, new_slots) = (new_ctrl?, new_slots?);
+
+		let old_ctrl = core::mem::replace(&mut self.ctrl, new_ctrl);
+		let old_slots = core::mem::replace(&mut self.slots, new_slots);
+		self.used = self.len;
+
+		for (ctrl, slot) in old_ctrl.into_iter().zip(old_slots.into_iter()) {
+			if ctrl == CTRL_EMPTY || ctrl == CTRL_DELETED {
+				continue;
+			}
+			let (k, v) = slot.unwrap();
+			let hash = self.hash_of(&k);
+			let idx = self.probe_insert_slot(hash);
+			self.ctrl[idx] = split_hash(hash).1;
+			self.slots[idx] = Some((k, v));
+		}
+
+		Ok(())
+	}
+
+	/// Finds the slot matching key `k`, scanning control bytes and stopping at the first `EMPTY`
+	/// slot encountered along the probe sequence.
+	fn find_slot<Q: ?Sized>(&self, k: &Q) -> Option<usize>
 	where
 		K: Borrow<Q>,
-		Q: Hash,
+		Q: Hash + Eq,
 	{
-		let mut hasher = XORHasher::new();
-		k.hash(&mut hasher);
-		(hasher.finish() % (self.buckets_count as u64)) as usize
+		let capacity = self.ctrl.len();
+		if capacity == 0 {
+			return None;
+		}
+
+		let (h1, h2) = split_hash(self.hash_of(k));
+		let mask = capacity - 1;
+		let mut idx = h1 & mask;
+
+		for _ in 0..capacity {
+			let ctrl = self.ctrl[idx];
+			if ctrl == CTRL_EMPTY {
+				return None;
+			}
+			if ctrl == h2 {
+				if let Some((key, _)) = &self.slots[idx] {
+					if key.borrow() == k {
+						return Some(idx);
+					}
+				}
+			}
+			idx = (idx + 1) & mask;
+		}
+
+		None
+	}
+
+	/// Finds the slot to use to insert a new key that is known not to be present yet: the first
+	/// `EMPTY` or `DELETED` slot along the probe sequence. The table must have free capacity.
+	fn probe_insert_slot(&self, hash: u64) -> usize {
+		let capacity = self.ctrl.len();
+		let (h1, _) = split_hash(hash);
+		let mask = capacity - 1;
+		let mut idx = h1 & mask;
+
+		loop {
+			let ctrl = self.ctrl[idx];
+			if ctrl == CTRL_EMPTY || ctrl == CTRL_DELETED {
+				return idx;
+			}
+			idx = (idx + 1) & mask;
+		}
+	}
+
+	/// Finds either the slot occupied by key `k`, or the slot that should receive it should it be
+	/// inserted (the first `EMPTY` or `DELETED` slot along the probe sequence).
+	fn find_or_insert_slot(&self, k: &K) -> (Option<usize>, usize) {
+		let capacity = self.ctrl.len();
+		let (h1, h2) = split_hash(self.hash_of(k));
+		let mask = capacity - 1;
+		let mut idx = h1 & mask;
+		let mut insert_at = None;
+
+		loop {
+			let ctrl = self.ctrl[idx];
+			if ctrl == CTRL_EMPTY {
+				return (None, insert_at.unwrap_or(idx));
+			}
+			if ctrl == CTRL_DELETED {
+				if insert_at.is_none() {
+					insert_at = Some(idx);
+				}
+			} else if ctrl == h2 {
+				if let Some((key, _)) = &self.slots[idx] {
+					if key == k {
+						return (Some(idx), idx);
+					}
+				}
+			}
+			idx = (idx + 1) & mask;
+		}
 	}
 
 	/// Returns an immutable reference to the value with the given key `k`.
@@ -234,13 +356,8 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
 		K: Borrow<Q>,
 		Q: Hash + Eq,
 	{
-		let index = self.get_bucket_index(k);
-
-		if index < self.buckets.len() {
-			self.buckets[index].get(k)
-		} else {
-			None
-		}
+		let idx = self.find_slot(k)?;
+		self.slots[idx].as_ref().map(|(_, v)| v)
 	}
 
 	/// Returns a mutable reference to the value with the given key `k`.
@@ -251,13 +368,8 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
 		K: Borrow<Q>,
 		Q: Hash + Eq,
 	{
-		let index = self.get_bucket_index(k);
-
-		if index < self.buckets.len() {
-			self.buckets[index].get_mut(k)
-		} else {
-			None
-		}
+		let idx = self.find_slot(k)?;
+		self.slots[idx].as_mut().map(|(_, v)| v)
 	}
 
 	/// Tells whether the hash map contains the given key `k`.
@@ -270,38 +382,48 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
 		self.get(k).is_some()
 	}
 
-	/// Creates an iterator of immutable references for the hash map.
-	#[inline]
-	pub fn iter(&self) -> Iter<K, V> {
-		Iter {
-			hm: self,
-
-			curr_bucket: 0,
-			curr_element: 0,
-			i: 0,
-		}
-	}
-
 	/// Inserts a new element into the hash map.
 	///
 	/// If the key was already present, the function returns the previous value.
 	pub fn insert(&mut self, k: K, v: V) -> AllocResult<Option<V>> {
-		let index = self.get_bucket_index(&k);
-		if index >= self.buckets.len() {
-			// Creating buckets
-			let begin = self.buckets.len();
-			for i in begin..=index {
-				self.buckets.insert(i, Bucket::new())?;
-			}
-		}
+		self.reserve_one()?;
 
-		let result = self.buckets[index].insert(k, v)?;
+		let (occupied, idx) = self.find_or_insert_slot(&k);
+		if let Some(idx) = occupied {
+			let (_, old) = self.slots[idx].replace((k, v)).unwrap();
+			return Ok(Some(old));
+		}
 
-		if result.is_none() {
-			self.len += 1;
+		let was_empty = self.ctrl[idx] == CTRL_EMPTY;
+		self.ctrl[idx] = split_hash(self.hash_of(&k)).1;
+		self.slots[idx] = Some((k, v));
+		self.len += 1;
+		if was_empty {
+			self.used += 1;
 		}
 
-		Ok(result)
+		Ok(None)
+	}
+
+	/// Gets the given key's corresponding entry in the map for in-place insert-or-update.
+	///
+	/// The slot is resolved by a single probe; a subsequent [`Entry::or_insert`]-style call reads
+	/// or writes it directly with no rehash or further scanning.
+	pub fn entry(&mut self, key: K) -> AllocResult<Entry<K, V, S>> {
+		self.reserve_one()?;
+
+		let (occupied, idx) = self.find_or_insert_slot(&key);
+		Ok(match occupied {
+			Some(idx) => Entry::Occupied(OccupiedEntry {
+				map: self,
+				idx,
+			}),
+			None => Entry::Vacant(VacantEntry {
+				map: self,
+				idx,
+				key,
+			}),
+		})
 	}
 
 	/// Removes an element from the hash map.
@@ -312,44 +434,135 @@ impl<K: Eq + Hash, V> HashMap<K, V> {
 		K: Borrow<Q>,
 		Q: Hash + Eq,
 	{
-		let index = self.get_bucket_index(k);
+		let idx = self.find_slot(k)?;
 
-		if index < self.buckets.len() {
-			let result = self.buckets[index].remove(k);
+		self.ctrl[idx] = CTRL_DELETED;
+		self.len -= 1;
+		let (_, v) = self.slots[idx].take().unwrap();
+		Some(v)
+	}
+}
 
-			if result.is_some() {
-				self.len -= 1;
-			}
+/// A view into a single entry of a [`HashMap`], which may either be vacant or occupied.
+///
+/// This enum mirrors [`std::collections::hash_map::Entry`] and is returned by
+/// [`HashMap::entry`].
+pub enum Entry<'m, K: Eq + Hash, V, S> {
+	/// The entry is occupied: the key is already present in the map.
+	Occupied(OccupiedEntry<'m, K, V, S>),
+	/// The entry is vacant: the key is absent from the map.
+	Vacant(VacantEntry<'m, K, V, S>),
+}
 
-			result
-		} else {
-			None
+impl<'m, K: Eq + Hash, V, S: BuildHasher> Entry<'m, K, V, S> {
+	/// Ensures a value is present in the entry, inserting `default` if it is vacant.
+	///
+	/// Returns a mutable reference to the value. Since the map uses fallible allocation, the
+	/// insertion may fail and is thus reported through the returned result.
+	pub fn or_insert(self, default: V) -> AllocResult<&'m mut V> {
+		match self {
+			Self::Occupied(e) => Ok(e.into_mut()),
+			Self::Vacant(e) => e.insert(default),
 		}
 	}
 
-	/// Retains only the elements for which the given predicate returns `true`.
-	pub fn retain<F: FnMut(&K, &mut V) -> bool>(&mut self, mut f: F) {
-		let mut len = 0;
+	/// Same as [`Self::or_insert`], but the default value is computed lazily if the entry is
+	/// vacant.
+	pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> AllocResult<&'m mut V> {
+		match self {
+			Self::Occupied(e) => Ok(e.into_mut()),
+			Self::Vacant(e) => e.insert(default()),
+		}
+	}
 
-		for b in self.buckets.iter_mut() {
-			b.elements.retain(|(k, v): &mut (K, V)| f(k, &mut *v));
-			len += b.elements.len();
+	/// Calls `f` with a mutable reference to the value if the entry is occupied, then returns
+	/// the entry unchanged so it can be chained with [`Self::or_insert`].
+	pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+		match self {
+			Self::Occupied(mut e) => {
+				f(e.get_mut());
+				Self::Occupied(e)
+			}
+			Self::Vacant(e) => Self::Vacant(e),
 		}
+	}
+}
 
-		self.len = len;
+/// An occupied entry of a [`HashMap`], as returned by [`HashMap::entry`].
+///
+/// The slot index is cached so that accessing or replacing the value is O(1) with no rehash.
+pub struct OccupiedEntry<'m, K: Eq + Hash, V, S> {
+	/// The map the entry belongs to.
+	map: &'m mut HashMap<K, V, S>,
+	/// The index of the slot containing the entry.
+	idx: usize,
+}
+
+impl<'m, K: Eq + Hash, V, S> OccupiedEntry<'m, K, V, S> {
+	/// Returns an immutable reference to the entry's key.
+	pub fn key(&self) -> &K {
+		&self.map.slots[self.idx].as_ref().unwrap().0
 	}
 
-	/// Drops all elements in the hash map.
-	pub fn clear(&mut self) {
-		for i in 0..self.buckets.len() {
-			self.buckets[i].elements.clear();
+	/// Returns an immutable reference to the entry's value.
+	pub fn get(&self) -> &V {
+		&self.map.slots[self.idx].as_ref().unwrap().1
+	}
+
+	/// Returns a mutable reference to the entry's value, borrowing the entry.
+	pub fn get_mut(&mut self) -> &mut V {
+		&mut self.map.slots[self.idx].as_mut().unwrap().1
+	}
+
+	/// Consumes the entry, returning a mutable reference to the value bound to the map's
+	/// lifetime.
+	pub fn into_mut(self) -> &'m mut V {
+		&mut self.map.slots[self.idx].as_mut().unwrap().1
+	}
+
+	/// Replaces the entry's value, returning the previous one.
+	pub fn insert(&mut self, value: V) -> V {
+		core::mem::replace(self.get_mut(), value)
+	}
+}
+
+/// A vacant entry of a [`HashMap`], as returned by [`HashMap::entry`].
+pub struct VacantEntry<'m, K: Eq + Hash, V, S> {
+	/// The map the entry belongs to.
+	map: &'m mut HashMap<K, V, S>,
+	/// The index of the slot that shall receive the entry.
+	idx: usize,
+	/// The entry's key.
+	key: K,
+}
+
+impl<'m, K: Eq + Hash, V, S: BuildHasher> VacantEntry<'m, K, V, S> {
+	/// Returns an immutable reference to the entry's key.
+	pub fn key(&self) -> &K {
+		&self.key
+	}
+
+	/// Inserts the entry's key with the given `value`, returning a mutable reference to it.
+	///
+	/// Since the map uses fallible allocation, the insertion may fail and is thus reported
+	/// through the returned result.
+	pub fn insert(self, value: V) -> AllocResult<&'m mut V> {
+		let idx = self.idx;
+		let map = self.map;
+
+		let was_empty = map.ctrl[idx] == CTRL_EMPTY;
+		map.ctrl[idx] = split_hash(map.hash_of(&self.key)).1;
+		map.slots[idx] = Some((self.key, value));
+		map.len += 1;
+		if was_empty {
+			map.used += 1;
 		}
 
-		self.len = 0;
+		Ok(&mut map.slots[idx].as_mut().unwrap().1)
 	}
 }
 
-impl<K: Eq + Hash, V> Index<K> for HashMap<K, V> {
+impl<K: Eq + Hash, V, S: BuildHasher> Index<K> for HashMap<K, V, S> {
 	type Output = V;
 
 	#[inline]
@@ -358,24 +571,40 @@ impl<K: Eq + Hash, V> Index<K> for HashMap<K, V> {
 	}
 }
 
-impl<K: Eq + Hash, V> IndexMut<K> for HashMap<K, V> {
+impl<K: Eq + Hash, V, S: BuildHasher> IndexMut<K> for HashMap<K, V, S> {
 	#[inline]
 	fn index_mut(&mut self, k: K) -> &mut Self::Output {
 		self.get_mut(&k).expect("no entry found for key")
 	}
 }
 
-impl<K: Eq + Hash + TryClone<Error = E>, V: TryClone<Error = E>, E: From<AllocError>> TryClone
-	for HashMap<K, V>
+impl<
+		K: Eq + Hash + TryClone<Error = E>,
+		V: TryClone<Error = E>,
+		S: Clone,
+		E: From<AllocError>,
+	> TryClone for HashMap<K, V, S>
 {
 	type Error = E;
 
 	fn try_clone(&self) -> Result<Self, Self::Error> {
+		let mut slots = Vec::with_capacity(self.slots.len())?;
+		for slot in self.slots.iter() {
+			let cloned = match slot {
+				Some((k, v)) => Some((k.try_clone()?, v.try_clone()?)),
+				None => None,
+			};
+			slots.push(cloned)?;
+		}
+
 		Ok(Self {
-			buckets_count: self.buckets_count,
-			buckets: self.buckets.try_clone()?,
+			ctrl: self.ctrl.try_clone()?,
+			slots,
 
 			len: self.len,
+			used: self.used,
+
+			hash_builder: self.hash_builder.clone(),
 		})
 	}
 }
@@ -384,50 +613,31 @@ impl<K: Eq + Hash + TryClone<Error = E>, V: TryClone<Error = E>, E: From<AllocEr
 ///
 /// This iterator doesn't guarantee any order since the HashMap itself doesn't store value in a
 /// specific order.
-pub struct Iter<'m, K: Hash + Eq, V> {
+pub struct Iter<'m, K: Hash + Eq, V, S> {
 	/// The hash map to iterate into.
-	hm: &'m HashMap<K, V>,
+	hm: &'m HashMap<K, V, S>,
 
-	/// The current bucket index.
-	curr_bucket: usize,
-	/// The current element index.
-	curr_element: usize,
+	/// The current slot index.
+	curr: usize,
 	/// Number of elements iterated on so far
 	i: usize,
 }
 
-impl<'m, K: Hash + Eq, V> Iterator for Iter<'m, K, V> {
+impl<'m, K: Hash + Eq, V, S> Iterator for Iter<'m, K, V, S> {
 	type Item = (&'m K, &'m V);
 
 	fn next(&mut self) -> Option<Self::Item> {
-		if self.curr_bucket >= self.hm.buckets.len() {
-			return None;
-		}
-
-		// If the last element has been reached, getting the next non-empty bucket
-		if self.curr_element >= self.hm.buckets[self.curr_bucket].elements.len() {
-			self.curr_element = 0;
-			self.curr_bucket += 1;
+		while self.curr < self.hm.ctrl.len() {
+			let idx = self.curr;
+			self.curr += 1;
 
-			for i in self.curr_bucket..self.hm.buckets.len() {
-				if !self.hm.buckets[i].elements.is_empty() {
-					break;
-				}
-
-				self.curr_bucket += 1;
-			}
-
-			if self.curr_bucket >= self.hm.buckets.len() {
-				return None;
+			if let Some((k, v)) = &self.hm.slots[idx] {
+				self.i += 1;
+				return Some((k, v));
 			}
 		}
 
-		let (k, v) = self.hm.buckets[self.curr_bucket]
-			.elements
-			.index(self.curr_element);
-		self.curr_element += 1;
-		self.i += 1;
-		Some((k, v))
+		None
 	}
 
 	fn count(self) -> usize {
@@ -442,17 +652,60 @@ impl<'m, K: Hash + Eq, V> Iterator for Iter<'m, K, V> {
 
 // TODO implement DoubleEndedIterator
 
-impl<'m, K: Hash + Eq, V> ExactSizeIterator for Iter<'m, K, V> {
+impl<'m, K: Hash + Eq, V, S> ExactSizeIterator for Iter<'m, K, V, S> {
 	fn len(&self) -> usize {
 		self.hm.len()
 	}
 }
 
-impl<'m, K: Hash + Eq, V> FusedIterator for Iter<'m, K, V> {}
+impl<'m, K: Hash + Eq, V, S> FusedIterator for Iter<'m, K, V, S> {}
+
+unsafe impl<'m, K: Hash + Eq, V, S> TrustedLen for Iter<'m, K, V, S> {}
+
+/// An owning iterator for the [`HashMap`] structure, yielding each key/value pair by value.
+///
+/// This is notably used to redistribute entries into a different number of buckets (e.g. when
+/// [`super::concurrent_hashmap::ConcurrentHashMap`] resizes its shards) without requiring `K`/`V`
+/// to be [`TryClone`].
+pub struct IntoIter<K: Eq + Hash, V, S> {
+	/// The hash map being drained.
+	hm: HashMap<K, V, S>,
+	/// The current slot index.
+	curr: usize,
+}
+
+impl<K: Eq + Hash, V, S> Iterator for IntoIter<K, V, S> {
+	type Item = (K, V);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		while self.curr < self.hm.ctrl.len() {
+			let idx = self.curr;
+			self.curr += 1;
+
+			if let Some(kv) = self.hm.slots[idx].take() {
+				return Some(kv);
+			}
+		}
+
+		None
+	}
+}
+
+impl<K: Eq + Hash, V, S> IntoIterator for HashMap<K, V, S> {
+	type Item = (K, V);
+	type IntoIter = IntoIter<K, V, S>;
 
-unsafe impl<'m, K: Hash + Eq, V> TrustedLen for Iter<'m, K, V> {}
+	fn into_iter(self) -> Self::IntoIter {
+		IntoIter {
+			hm: self,
+			curr: 0,
+		}
+	}
+}
 
-impl<K: Eq + Hash + fmt::Display, V: fmt::Display> fmt::Display for HashMap<K, V> {
+impl<K: Eq + Hash + fmt::Display, V: fmt::Display, S: BuildHasher> fmt::Display
+	for HashMap<K, V, S>
+{
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		write!(f, "[")?;
 
@@ -509,4 +762,75 @@ mod test {
 			assert_eq!(hash_map.len(), i);
 		}
 	}
+
+	#[test_case]
+	fn hash_map_entry_vacant() {
+		let mut hash_map = HashMap::<u32, u32>::new();
+
+		assert_eq!(*hash_map.entry(0).unwrap().or_insert(42).unwrap(), 42);
+		assert_eq!(hash_map.len(), 1);
+		assert_eq!(*hash_map.get(&0).unwrap(), 42);
+	}
+
+	#[test_case]
+	fn hash_map_entry_occupied() {
+		let mut hash_map = HashMap::<u32, u32>::new();
+		hash_map.insert(0, 1).unwrap();
+
+		hash_map.entry(0).unwrap().and_modify(|v| *v += 1);
+		assert_eq!(*hash_map.get(&0).unwrap(), 2);
+
+		assert_eq!(*hash_map.entry(0).unwrap().or_insert(0).unwrap(), 2);
+		assert_eq!(hash_map.len(), 1);
+	}
+
+	#[test_case]
+	fn hash_map_grow() {
+		let mut hash_map = HashMap::<u32, u32>::new();
+
+		// Insert enough elements to force several grows.
+		for i in 0..1000 {
+			hash_map.insert(i, i * 2).unwrap();
+		}
+		assert_eq!(hash_map.len(), 1000);
+		assert!(hash_map.get_buckets_count() >= 1000);
+
+		for i in 0..1000 {
+			assert_eq!(*hash_map.get(&i).unwrap(), i * 2);
+		}
+	}
+
+	#[test_case]
+	fn hash_map_tombstone_reuse() {
+		let mut hash_map = HashMap::<u32, u32>::new();
+
+		for i in 0..50 {
+			hash_map.insert(i, i).unwrap();
+		}
+		for i in 0..25 {
+			hash_map.remove(&i).unwrap();
+		}
+		for i in 0..25 {
+			hash_map.insert(i, i + 1000).unwrap();
+		}
+
+		assert_eq!(hash_map.len(), 50);
+		for i in 0..25 {
+			assert_eq!(*hash_map.get(&i).unwrap(), i + 1000);
+		}
+		for i in 25..50 {
+			assert_eq!(*hash_map.get(&i).unwrap(), i);
+		}
+	}
+
+	#[test_case]
+	fn fnv_hasher_differs_on_permutation() {
+		fn hash(bytes: &[u8]) -> u64 {
+			let mut hasher = FnvHasher::default();
+			hasher.write(bytes);
+			hasher.finish()
+		}
+
+		assert_ne!(hash(&[1, 2, 3]), hash(&[3, 2, 1]));
+	}
 }
@@ -0,0 +1,147 @@
+//! A reader/writer lock allows several readers to access a resource concurrently, but requires
+//! exclusive access for a writer.
+//!
+//! Unlike [`super::Mutex`], which serializes every access, this is useful for data that is read
+//! far more often than it is written, since concurrent readers never block each other.
+
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+use core::ops::DerefMut;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+/// The state value meaning the lock is held by a writer.
+const WRITER: usize = usize::MAX;
+
+/// A reader/writer lock.
+pub struct RwLock<T: ?Sized> {
+	/// `0` if free, `WRITER` if write-locked, otherwise the number of active readers.
+	state: AtomicUsize,
+	/// The data protected by the lock.
+	data: UnsafeCell<T>,
+}
+
+// Safe because access to the inner data is synchronized by `state`: a writer gets exclusive
+// `&mut T` access, but several readers can hold a shared `&T` at once, so `T` must also be `Sync`
+// (the same bound `std::sync::RwLock` requires).
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+	/// Creates a new instance, wrapping the given data.
+	pub const fn new(data: T) -> Self {
+		Self {
+			state: AtomicUsize::new(0),
+			data: UnsafeCell::new(data),
+		}
+	}
+}
+
+impl<T: ?Sized> RwLock<T> {
+	/// Locks the resource for reading, spinning until no writer holds the lock.
+	///
+	/// Several readers may hold the lock at the same time.
+	pub fn read(&self) -> RwLockReadGuard<T> {
+		loop {
+			let state = self.state.load(Ordering::Relaxed);
+			if state != WRITER
+				&& self
+					.state
+					.compare_exchange_weak(
+						state,
+						state + 1,
+						Ordering::Acquire,
+						Ordering::Relaxed,
+					)
+					.is_ok()
+			{
+				break;
+			}
+
+			core::hint::spin_loop();
+		}
+
+		RwLockReadGuard {
+			lock: self,
+		}
+	}
+
+	/// Locks the resource for writing, spinning until no reader nor writer holds the lock.
+	pub fn write(&self) -> RwLockWriteGuard<T> {
+		while self
+			.state
+			.compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+			.is_err()
+		{
+			core::hint::spin_loop();
+		}
+
+		RwLockWriteGuard {
+			lock: self,
+		}
+	}
+}
+
+/// An RAII guard giving shared (read-only) access to a [`RwLock`]'s content.
+pub struct RwLockReadGuard<'r, T: ?Sized> {
+	/// The lock the guard comes from.
+	lock: &'r RwLock<T>,
+}
+
+impl<'r, T: ?Sized> RwLockReadGuard<'r, T> {
+	/// Returns an immutable reference to the protected data.
+	pub fn get(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+}
+
+impl<'r, T: ?Sized> Deref for RwLockReadGuard<'r, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		self.get()
+	}
+}
+
+impl<'r, T: ?Sized> Drop for RwLockReadGuard<'r, T> {
+	fn drop(&mut self) {
+		self.lock.state.fetch_sub(1, Ordering::Release);
+	}
+}
+
+/// An RAII guard giving exclusive (read/write) access to a [`RwLock`]'s content.
+pub struct RwLockWriteGuard<'r, T: ?Sized> {
+	/// The lock the guard comes from.
+	lock: &'r RwLock<T>,
+}
+
+impl<'r, T: ?Sized> RwLockWriteGuard<'r, T> {
+	/// Returns an immutable reference to the protected data.
+	pub fn get(&self) -> &T {
+		unsafe { &*self.lock.data.get() }
+	}
+
+	/// Returns a mutable reference to the protected data.
+	pub fn get_mut(&mut self) -> &mut T {
+		unsafe { &mut *self.lock.data.get() }
+	}
+}
+
+impl<'r, T: ?Sized> Deref for RwLockWriteGuard<'r, T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		self.get()
+	}
+}
+
+impl<'r, T: ?Sized> DerefMut for RwLockWriteGuard<'r, T> {
+	fn deref_mut(&mut self) -> &mut T {
+		self.get_mut()
+	}
+}
+
+impl<'r, T: ?Sized> Drop for RwLockWriteGuard<'r, T> {
+	fn drop(&mut self) {
+		self.lock.state.store(0, Ordering::Release);
+	}
+}